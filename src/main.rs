@@ -1,22 +1,62 @@
-use modules::config::get_arguments;
+use modules::config::{get_arguments, Command, InputFormat};
 use modules::dynamo::Dynamo;
-use modules::utility::parse_csv;
+use modules::error::Result;
+use modules::export::Exporter;
+use modules::infer::infer_types;
+use modules::utility::{parse_csv, parse_json_lines};
 use std::process::exit;
 
 mod modules;
 
+// only problems which stop the import exit with error, see exit_code
 #[tokio::main]
 async fn main() {
-    let (filename, config) = get_arguments();
+    if let Err(error) = run().await {
+        println!("{}", error);
+        exit(error.exit_code());
+    }
+}
 
-    println!("Reading csv...");
-    let (header, rows) = parse_csv(filename);
+async fn run() -> Result<()> {
+    match get_arguments()? {
+        Command::Import(filename, config) if config.input_format == InputFormat::JsonLines => {
+            println!("Reading json...");
+            let items = parse_json_lines(filename)?;
 
-    if header.is_empty() || rows.is_empty() {
-        println!("Empty csv, exiting...");
-        exit(0);
-    }
+            if items.is_empty() {
+                println!("Empty json, exiting...");
+                return Ok(());
+            }
+
+            let mut client = Dynamo::new(config)?;
+            client.save_items_to_dynamo(&items).await
+        }
+        Command::Import(filename, config) => {
+            println!("Reading csv...");
+            let (header, rows, lines) = parse_csv(filename)?;
 
-    let mut client = Dynamo::new(config);
-    client.save_to_dynamo(&header, &rows).await;
+            if header.is_empty() || rows.is_empty() {
+                println!("Empty csv, exiting...");
+                return Ok(());
+            }
+
+            let mut client = Dynamo::new(config)?;
+            client.save_to_dynamo(&header, &rows, &lines).await
+        }
+        Command::Query(config) => {
+            let exporter = Exporter::new(config)?;
+            exporter.query_to_csv().await
+        }
+        Command::Infer(config) => {
+            println!("Reading csv...");
+            let (header, rows, lines) = parse_csv(config.filename.to_owned())?;
+
+            if header.is_empty() || rows.is_empty() {
+                println!("Empty csv, exiting...");
+                return Ok(());
+            }
+
+            infer_types(config, &header, &rows, &lines)
+        }
+    }
 }
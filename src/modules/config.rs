@@ -1,8 +1,35 @@
+use super::error::{Error, Result};
+use super::schema::{load_schema, EmptyPolicy, Schema};
+use super::timestamp::parse_offset;
 use super::utility::{check_range, read_int, read_text, read_yes_or_no};
-use clap::clap_app;
-use std::env;
+use clap::{clap_app, ArgMatches};
+use rusoto_dynamodb::AttributeValue;
+use serde_json::from_str;
+use std::{collections::HashMap, env};
+
+pub enum Command {
+    // import a csv file into a table
+    Import(String, Config),
+
+    // export the result of a query into a csv file
+    Query(QueryConfig),
+
+    // report inferred types of all columns, without uploading
+    Infer(InferConfig),
+}
+
+#[derive(PartialEq)]
+pub enum InputFormat {
+    Csv,
+
+    // DynamoDB JSON, one item per line. Either {"Item": {...}} as in
+    // the export to S3, or the item itself. Gzip files are accepted
+    JsonLines,
+}
 
 pub struct Config {
+    pub filename: String,
+    pub input_format: InputFormat,
     pub region: String,
     pub table_name: String,
     pub batch_size: usize,
@@ -31,110 +58,486 @@ pub struct Config {
         When allow_null is false, all null values will be ignored.
     */
     pub allow_null: bool,
+
+    /*
+        option: empty_policy
+        default: none
+        effect: empty cells are omitted, saved as null, saved as empty string
+        or rejected. Without a policy, empty cells are parsed like other values,
+        which means empty string for inferred columns.
+        Policy of a column in schema overrides this option.
+    */
+    pub empty_policy: Option<EmptyPolicy>,
+
+    /*
+        option: detect_dynamodb_json
+        default: false
+        effect: cells of inferred columns which are valid DynamoDB JSON,
+        e.g. {"S": "x"} or {"NS": ["1", "2"]}, are saved as they are.
+        Other cells are inferred as usual.
+        Columns can also be declared as DynamoDB JSON in schema.
+    */
+    pub detect_dynamodb_json: bool,
+
+    /*
+        option: nested
+        default: false
+        effect: headers like address.city and tags[0] are built into nested
+        maps and lists, instead of being used as attribute names
+    */
+    pub nested: bool,
+
+    /*
+        option: schema
+        default: empty
+        effect: columns declared in schema are converted to the given type
+        instead of being inferred. See schema.rs for the format.
+    */
+    pub schema: Schema,
+
+    /*
+        option: ttl
+        default: none
+        effect: an attribute of epoch seconds is added to every item, either
+        relative to import time or parsed from a date column.
+    */
+    pub ttl: Option<TtlConfig>,
+
+    /*
+        option: constants
+        default: none
+        effect: attributes of the same value added to every item, e.g. --set source=crm
+        types are inferred, or declared as header annotations, e.g. --set version:N=3
+    */
+    pub constants: Vec<(String, String)>,
+
+    /*
+        option: add_metadata
+        default: false
+        effect: attributes to trace an import are added to every item:
+        _import_id (uuid of the import), _imported_at (iso8601 in UTC),
+        _source_file and _source_line (line of the row in csv)
+    */
+    pub add_metadata: bool,
+
+    /*
+        option: strict
+        default: false
+        effect: the import is aborted if any row violates validation rules
+        in schema, before anything is uploaded. Otherwise such rows are
+        saved with failed items, together with the violated rule
+    */
+    pub strict: bool,
+
+    /*
+        option: script
+        default: none
+        effect: a rhai script which defines transform(row), called with each row
+        (after mapping and lookups) as a map of column name to cell text.
+        It returns a map, an array of maps or () to build any number of rows,
+        which are then validated and typed like rows of csv
+    */
+    pub script: Option<String>,
+}
+
+pub struct TtlConfig {
+    pub attribute_name: String,
+    pub source: TtlSource,
+}
+
+pub enum TtlSource {
+    // seconds after import time
+    Offset(i64),
+
+    // date column, with its format if given (otherwise timestamp of the column
+    // in schema, or rfc3339)
+    Column(String, Option<String>),
+}
+
+pub struct QueryConfig {
+    pub region: String,
+    pub table_name: String,
+    pub index_name: Option<String>,
+    pub key_condition: String,
+    pub filter_expression: Option<String>,
+
+    // placeholders used in the expressions, e.g. {":pk": {"S": "USER#1"}}
+    // None if not given, as DynamoDB rejects an empty map
+    pub attribute_values: Option<HashMap<String, AttributeValue>>,
+    pub attribute_names: Option<HashMap<String, String>>,
+
+    pub output_filename: String,
+}
+
+pub struct InferConfig {
+    pub filename: String,
+
+    // only infer types of evenly spaced rows, instead of every row
+    pub sample_size: Option<usize>,
+    pub allow_set: bool,
+    pub empty_policy: Option<EmptyPolicy>,
+    pub detect_dynamodb_json: bool,
+    pub schema: Schema,
+
+    // given by --schema, options in it are kept in the output schema
+    pub schema_filename: Option<String>,
+
+    // write dominant types of columns into a schema file
+    pub output_filename: Option<String>,
 }
 
 pub const LOG_FILE_NAME: &str = "dynamodb_logs.txt";
 pub const FAILED_CSV_FILE_NAME: &str = "failed_items.csv";
+pub const FAILED_JSON_FILE_NAME: &str = "failed_items.json";
 pub const BATCH_SIZE_MIN: usize = 1;
 pub const BATCH_SIZE_MAX: usize = 25;
 pub const BATCH_SIZE_DEFAULT: &str = "10";
 pub const BATCH_INTERVAL_MIN: usize = 0;
 pub const BATCH_INTERVAL_MAX: usize = 30000;
 pub const BATCH_INTERVAL_DEFAULT: &str = "50";
+pub const QUERY_OUTPUT_DEFAULT: &str = "query_result.csv";
+const RESERVED_ARGS: [&str; 6] = ["-h", "--help", "-V", "--version", "query", "infer"];
+
+impl InputFormat {
+    // json lines are recognised by extension, e.g. data.json, data.jsonl, data.json.gz
+    pub fn from_filename(filename: &str) -> InputFormat {
+        let lowercase = filename.to_lowercase();
+        let name = lowercase.trim_end_matches(".gz");
+        if name.ends_with(".json") || name.ends_with(".jsonl") {
+            InputFormat::JsonLines
+        } else {
+            InputFormat::Csv
+        }
+    }
 
-pub fn get_arguments() -> (String, Config) {
+    // failed items are saved in the same format as input
+    pub fn failed_file_name(&self) -> &str {
+        match self {
+            InputFormat::Csv => FAILED_CSV_FILE_NAME,
+            InputFormat::JsonLines => FAILED_JSON_FILE_NAME,
+        }
+    }
+}
+
+pub fn get_arguments() -> Result<Command> {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() == 2
-        && args[1] != "-h"
-        && args[1] != "--help"
-        && args[1] != "-V"
-        && args[1] != "--version"
-    {
-        get_arguments_interactive_mode(args[1].to_string())
+    // a single argument is treated as filename, unless it's a flag or subcommand
+    if args.len() == 2 && !RESERVED_ARGS.contains(&args[1].as_str()) {
+        let (filename, config) = get_arguments_interactive_mode(args[1].to_string())?;
+        Ok(Command::Import(filename, config))
     } else {
         get_arguments_command_mode()
     }
 }
 
-fn get_arguments_command_mode() -> (String, Config) {
+fn get_arguments_command_mode() -> Result<Command> {
     let matches = clap_app!(x =>
         (name: "CSV_To_DynamoDB")
         (version: "0.1.2")
         (author: "Devin (github.com/devin-git)")
-        (@arg FILENAME: +required "Provide CSV filename")
+        (@setting SubcommandsNegateReqs)
+        (@arg FILENAME: +required "Provide CSV filename, or DynamoDB JSON lines (.json, .jsonl, .json.gz)")
         (@arg REGION: -r --region +required +takes_value "Specify AWS region. E.g. ap-southeast-2, ca-central-1, eu-north-1, sa-east-1, us-west-1, cn-north-1, etc.")
         (@arg TABLE: -t --table +required +takes_value "Specify DynamoDB table name")
         (@arg BATCH_SIZE: -s --size +takes_value "Specify batch size between 1 and 25. Default 10")
         (@arg BATCH_INTERVAL: -i --interval +takes_value "Specify batch interval in milliseconds between 0 and 30000. Default 50")
         (@arg ALLOW_SET: --allowset "Convert lists to sets whenever possible")
         (@arg ALLOW_NULL: --allownull "Allow null values to be saved. Without the flag, null values will be ignored")
+        (@arg EMPTY: --empty +takes_value "Policy of empty cells: omit, null, string or reject. Without the option, empty cells are inferred as empty string")
+        (@arg TRUE_TOKENS: --("true-values") +takes_value "Comma separated words of true, case insensitive, e.g. true,yes,y,1. Default true")
+        (@arg FALSE_TOKENS: --("false-values") +takes_value "Comma separated words of false, case insensitive, e.g. false,no,n,0. Default false")
+        (@arg NULL_TOKENS: --("null-values") +takes_value "Comma separated words of null, case insensitive, e.g. null,\\N,N/A. Default null")
+        (@arg DYNAMODB_JSON: --("dynamodb-json") "Save cells of valid DynamoDB JSON (e.g. {\"S\": \"x\"}) as they are, instead of inferring their type")
+        (@arg NESTED: --nested "Build nested maps and lists from headers like address.city and tags[0]")
+        (@arg SCHEMA: --schema +takes_value "Specify a json or toml file declaring the type of columns. Undeclared columns are inferred")
+        (@arg RENAME: --rename +takes_value +multiple number_of_values(1) "Rename a column before typing, e.g. --rename user_id=pk. Can be repeated")
+        (@arg DROP: --drop +takes_value +multiple number_of_values(1) "Drop a column, e.g. --drop row_no. Can be repeated")
+        (@arg TTL_ATTRIBUTE: --("ttl-attribute") +takes_value requires[TTL_FROM] "Add a TTL attribute of epoch seconds to every item. Should be the TTL attribute of the table")
+        (@arg TTL_FROM: --("ttl-from") +takes_value requires[TTL_ATTRIBUTE] "Either an offset from import time (e.g. +30d, +12h) or a date column")
+        (@arg TTL_FORMAT: --("ttl-format") +takes_value requires[TTL_FROM] "Format of the date column: rfc3339 (default), rfc2822, epoch, epoch_millis or strftime pattern")
+        (@arg SET: --set +takes_value +multiple number_of_values(1) "Add an attribute of the same value to every item, e.g. --set source=crm or --set version:N=3. Can be repeated")
+        (@arg METADATA: --metadata "Add _import_id, _imported_at, _source_file and _source_line to every item")
+        (@arg SCRIPT: --script +takes_value "Transform each row with transform(row) in a rhai script, which returns a map, an array of maps or ()")
+        (@arg STRICT: --strict "Abort the import if any row violates validation rules in schema, before uploading")
         (@arg PREVIEW: -p --preview "Preview the first record before uploading")
         (@arg NO_LOG: -n --nolog "Do not log requests and error messages. NOT RECOMMENDED")
+        (@subcommand query =>
+            (about: "Export the result of a query into csv, which can be imported again")
+            (@arg REGION: -r --region +required +takes_value "Specify AWS region")
+            (@arg TABLE: -t --table +required +takes_value "Specify DynamoDB table name")
+            (@arg INDEX_NAME: --("index-name") +takes_value "Query a secondary index instead of the table")
+            (@arg KEY_CONDITION: -k --("key-condition") +required +takes_value "Key condition expression. E.g. \"pk = :pk AND sk BETWEEN :a AND :b\"")
+            (@arg VALUES: -v --values +takes_value "Expression attribute values in DynamoDB JSON. E.g. {\":pk\": {\"S\": \"USER#1\"}}")
+            (@arg NAMES: --names +takes_value "Expression attribute names in JSON. E.g. {\"#s\": \"status\"}")
+            (@arg FILTER: -f --filter +takes_value "Filter expression applied after the key condition")
+            (@arg OUTPUT: -o --output +takes_value "Output csv filename. Default query_result.csv")
+        )
+        (@subcommand infer =>
+            (about: "Infer the type of every row, and report columns whose type varies across rows")
+            (@arg FILENAME: +required "Provide CSV filename")
+            (@arg SAMPLE: --sample +takes_value "Only infer types of the given number of rows, spread over the file")
+            (@arg ALLOW_SET: --allowset "Convert lists to sets whenever possible")
+            (@arg EMPTY: --empty +takes_value "Policy of empty cells: omit, null, string or reject")
+            (@arg DYNAMODB_JSON: --("dynamodb-json") "Save cells of valid DynamoDB JSON as they are")
+            (@arg TRUE_TOKENS: --("true-values") +takes_value "Comma separated words of true, case insensitive")
+            (@arg FALSE_TOKENS: --("false-values") +takes_value "Comma separated words of false, case insensitive")
+            (@arg NULL_TOKENS: --("null-values") +takes_value "Comma separated words of null, case insensitive")
+            (@arg SCHEMA: --schema +takes_value "Specify a json or toml file declaring the type of columns")
+            (@arg OUTPUT: -o --output +takes_value "Save dominant type of each column into a schema file")
+        )
     )
     .get_matches();
 
+    if let Some(query_matches) = matches.subcommand_matches("query") {
+        return Ok(Command::Query(get_query_config(query_matches)?));
+    }
+
+    if let Some(infer_matches) = matches.subcommand_matches("infer") {
+        return Ok(Command::Infer(get_infer_config(infer_matches)?));
+    }
+
+    let filename = matches.value_of("FILENAME").unwrap().to_string();
     let config = Config {
+        filename: filename.to_owned(),
+        input_format: InputFormat::from_filename(&filename),
         region: matches.value_of("REGION").unwrap().to_string(),
         table_name: matches.value_of("TABLE").unwrap().to_string(),
         batch_size: check_range(
-            matches
-                .value_of("BATCH_SIZE")
-                .unwrap_or(BATCH_SIZE_DEFAULT)
-                .parse()
-                .expect("Error: Batch size is not a valid number"),
+            parse_number(
+                matches.value_of("BATCH_SIZE").unwrap_or(BATCH_SIZE_DEFAULT),
+                "Batch size",
+            )?,
             BATCH_SIZE_MIN,
             BATCH_SIZE_MAX,
-        ),
+        )?,
         batch_interval: check_range(
-            matches
-                .value_of("BATCH_INTERVAL")
-                .unwrap_or(BATCH_INTERVAL_DEFAULT)
-                .parse()
-                .expect("Error: Batch Interval is not a valid number"),
+            parse_number(
+                matches
+                    .value_of("BATCH_INTERVAL")
+                    .unwrap_or(BATCH_INTERVAL_DEFAULT),
+                "Batch interval",
+            )?,
             BATCH_INTERVAL_MIN,
             BATCH_INTERVAL_MAX,
-        ) as u64,
+        )? as u64,
         should_preview_record: matches.is_present("PREVIEW"),
         enable_log: !matches.is_present("NO_LOG"),
         allow_set: matches.is_present("ALLOW_SET"),
         allow_null: matches.is_present("ALLOW_NULL"),
+        empty_policy: get_empty_policy(&matches)?,
+        detect_dynamodb_json: matches.is_present("DYNAMODB_JSON"),
+        nested: matches.is_present("NESTED"),
+        schema: get_schema(&matches)?,
+        ttl: match matches.value_of("TTL_ATTRIBUTE") {
+            Some(attribute_name) => Some(TtlConfig {
+                attribute_name: attribute_name.to_string(),
+                source: get_ttl_source(
+                    matches.value_of("TTL_FROM").unwrap(),
+                    matches.value_of("TTL_FORMAT"),
+                )?,
+            }),
+            None => None,
+        },
+        constants: get_constants(&matches)?,
+        add_metadata: matches.is_present("METADATA"),
+        strict: matches.is_present("STRICT"),
+        script: matches.value_of("SCRIPT").map(|x| x.to_string()),
+    };
+
+    Ok(Command::Import(filename, config))
+}
+
+// a non-negative integer given in command line
+fn parse_number(text: &str, name: &str) -> Result<usize> {
+    text.parse()
+        .map_err(|_| Error::Config(format!("{} {} is not a valid number", name, text)))
+}
+
+// schema file, with column mapping from command line added
+fn get_schema(matches: &ArgMatches) -> Result<Schema> {
+    let mut schema = match matches.value_of("SCHEMA") {
+        Some(filename) => load_schema(filename)?,
+        None => Schema::default(),
+    };
+
+    for mapping in matches.values_of("RENAME").into_iter().flatten() {
+        match mapping.split_once('=') {
+            Some((column_name, new_name)) if !column_name.is_empty() && !new_name.is_empty() => {
+                schema
+                    .rename
+                    .insert(column_name.to_string(), new_name.to_string());
+            }
+            _ => {
+                return Err(Error::Config(format!(
+                    "Invalid rename {}. Expected old_name=new_name",
+                    mapping
+                )));
+            }
+        }
+    }
+    schema.drop.extend(
+        matches
+            .values_of("DROP")
+            .into_iter()
+            .flatten()
+            .map(|x| x.to_string()),
+    );
+
+    // words in options replace global words in schema
+    let get_tokens = |name| {
+        matches
+            .value_of(name)
+            .map(|x: &str| x.split(',').map(|x| x.trim().to_string()).collect())
     };
+    if let Some(tokens) = get_tokens("TRUE_TOKENS") {
+        schema.tokens.true_tokens = Some(tokens);
+    }
+    if let Some(tokens) = get_tokens("FALSE_TOKENS") {
+        schema.tokens.false_tokens = Some(tokens);
+    }
+    if let Some(tokens) = get_tokens("NULL_TOKENS") {
+        schema.tokens.null_tokens = Some(tokens);
+    }
+    schema.check_tokens().map_err(|error| {
+        Error::Config(format!("Invalid words of true, false and null. {}", error))
+    })?;
 
-    (matches.value_of("FILENAME").unwrap().to_string(), config)
+    Ok(schema)
 }
 
-fn get_arguments_interactive_mode(filename: String) -> (String, Config) {
+// name=value pairs, the name may have a type annotation
+fn get_constants(matches: &ArgMatches) -> Result<Vec<(String, String)>> {
+    matches
+        .values_of("SET")
+        .into_iter()
+        .flatten()
+        .map(|x| match x.split_once('=') {
+            Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+            _ => Err(Error::Config(format!(
+                "Invalid constant {}. Expected name=value",
+                x
+            ))),
+        })
+        .collect()
+}
+
+fn get_empty_policy(matches: &ArgMatches) -> Result<Option<EmptyPolicy>> {
+    matches
+        .value_of("EMPTY")
+        .map(|x| x.parse().map_err(Error::Config))
+        .transpose()
+}
+
+// an offset starts with +, otherwise it's a column name
+fn get_ttl_source(ttl_from: &str, ttl_format: Option<&str>) -> Result<TtlSource> {
+    if ttl_from.starts_with('+') {
+        parse_offset(ttl_from)
+            .map(TtlSource::Offset)
+            .map_err(|error| Error::Config(format!("Invalid TTL. {}", error)))
+    } else {
+        Ok(TtlSource::Column(
+            ttl_from.to_string(),
+            ttl_format.map(|x| x.to_string()),
+        ))
+    }
+}
+
+fn get_query_config(matches: &ArgMatches) -> Result<QueryConfig> {
+    Ok(QueryConfig {
+        region: matches.value_of("REGION").unwrap().to_string(),
+        table_name: matches.value_of("TABLE").unwrap().to_string(),
+        index_name: matches.value_of("INDEX_NAME").map(|x| x.to_string()),
+        key_condition: matches.value_of("KEY_CONDITION").unwrap().to_string(),
+        filter_expression: matches.value_of("FILTER").map(|x| x.to_string()),
+        attribute_values: match matches.value_of("VALUES") {
+            Some(x) => Some(from_str(x).map_err(|error| {
+                Error::Config(format!(
+                    "Expression attribute values are not valid DynamoDB JSON. {}",
+                    error
+                ))
+            })?),
+            None => None,
+        },
+        attribute_names: match matches.value_of("NAMES") {
+            Some(x) => Some(from_str(x).map_err(|error| {
+                Error::Config(format!(
+                    "Expression attribute names are not a valid JSON map. {}",
+                    error
+                ))
+            })?),
+            None => None,
+        },
+        output_filename: matches
+            .value_of("OUTPUT")
+            .unwrap_or(QUERY_OUTPUT_DEFAULT)
+            .to_string(),
+    })
+}
+
+fn get_infer_config(matches: &ArgMatches) -> Result<InferConfig> {
+    Ok(InferConfig {
+        filename: matches.value_of("FILENAME").unwrap().to_string(),
+        sample_size: matches
+            .value_of("SAMPLE")
+            .map(|x| parse_number(x, "Sample size"))
+            .transpose()?,
+        allow_set: matches.is_present("ALLOW_SET"),
+        empty_policy: get_empty_policy(matches)?,
+        detect_dynamodb_json: matches.is_present("DYNAMODB_JSON"),
+        schema: get_schema(matches)?,
+        schema_filename: matches.value_of("SCHEMA").map(|x| x.to_string()),
+        output_filename: matches.value_of("OUTPUT").map(|x| x.to_string()),
+    })
+}
+
+fn get_arguments_interactive_mode(filename: String) -> Result<(String, Config)> {
     // initialise parameters for DynamoDB
-    let region = read_text("Input Region (eg. ap-southeast-2)");
-    let table_name = read_text("Input table name");
-    let batch_size = read_int("Input batch size", BATCH_SIZE_MIN, BATCH_SIZE_MAX);
+    let region = read_text("Input Region (eg. ap-southeast-2)")?;
+    let table_name = read_text("Input table name")?;
+    let batch_size = read_int("Input batch size", BATCH_SIZE_MIN, BATCH_SIZE_MAX)?;
     let batch_interval = read_int(
         "Input batch interval in milliseconds",
         BATCH_INTERVAL_MIN,
         BATCH_INTERVAL_MAX,
-    );
+    )?;
     let allow_set = read_yes_or_no(
         "Would you like to convert list to set whenever possible?",
         false,
-    );
+    )?;
+    let schema_filename = read_text("Input schema filename (leave empty to infer all types)")?;
     let should_preview_record = read_yes_or_no(
         "Would you like to preview the first record before uploading?",
         true,
-    );
+    )?;
     println!();
 
-    (
-        filename,
+    Ok((
+        filename.to_owned(),
         Config {
-            region: region,
-            table_name: table_name,
-            batch_size: batch_size,
+            filename: filename.to_owned(),
+            input_format: InputFormat::from_filename(&filename),
+            region,
+            table_name,
+            batch_size,
             batch_interval: batch_interval as u64,
-            should_preview_record: should_preview_record,
+            should_preview_record,
             enable_log: true,
-            allow_set: allow_set,
+            allow_set,
             allow_null: false,
+            empty_policy: None,
+            detect_dynamodb_json: false,
+            nested: false,
+            schema: if schema_filename.is_empty() {
+                Schema::default()
+            } else {
+                load_schema(&schema_filename)?
+            },
+            ttl: None,
+            constants: Vec::new(),
+            add_metadata: false,
+            strict: false,
+            script: None,
         },
-    )
+    ))
 }
@@ -1,146 +1,501 @@
-use super::config::{Config, FAILED_CSV_FILE_NAME, LOG_FILE_NAME};
-use super::parser::Parser;
-use super::utility::{read_yes_or_no, ProgressPrinter};
+use super::config::{Config, TtlSource, LOG_FILE_NAME};
+use super::error::{Error, Result};
+use super::group::{
+    build_group_attrs, check_group_rule, check_group_values, group_rows, grouped_columns, Record,
+};
+use super::lookup::load_lookups;
+use super::nested::{check_paths, parse_path, NestedItem, PathSegment};
+use super::number::canonical_number;
+use super::parser::{attr_to_text, Parser};
+use super::schema::ItemDefinition;
+use super::script::{build_rows, Script};
+use super::template::Template;
+use super::timestamp::{SourceTimezone, TimestampFormat, TimestampOutput};
+use super::utility::{format_csv_row, read_yes_or_no, ProgressPrinter, Row};
+use super::validation::Validator;
+use chrono::{SecondsFormat, Utc};
+use itertools::Itertools;
 use rusoto_core::RusotoError;
 use rusoto_dynamodb::{
-    BatchWriteItemError, BatchWriteItemInput, DescribeTableInput, DynamoDb, DynamoDbClient,
-    PutRequest, WriteRequest,
+    AttributeValue, BatchWriteItemError, BatchWriteItemInput, DescribeTableInput,
+    DescribeTimeToLiveInput, DynamoDb, DynamoDbClient, PutRequest, WriteRequest,
 };
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::File,
+    io,
     io::{BufWriter, Write},
-    process::exit,
     thread::sleep,
     time::Duration,
 };
+use uuid::Uuid;
+
+// names of metadata attributes, see add_metadata in config
+const METADATA_ATTRIBUTES: [&str; 4] =
+    ["_import_id", "_imported_at", "_source_file", "_source_line"];
+
+// last column of failed csv when reasons are saved, see save_reasons
+const FAILED_REASON_COLUMN: &str = "_error";
 
 pub struct Dynamo {
     client: DynamoDbClient,
     parser: Parser,
     config: Config,
     table_attrs: HashMap<String, String>,
+
+    // key attributes of the table, every item must have them
+    table_keys: Vec<String>,
+
+    // computed attributes shared by all items, appended to each row as extra columns
+    templates: Vec<(String, Template)>,
+
+    // how items are built from each row, one item unless fan-out is declared in schema
+    layouts: Vec<ItemLayout>,
     logger: BufWriter<File>,
-    csv_writer: BufWriter<File>,
+    failed_writer: BufWriter<File>,
+
+    // lines of failed records, so a record of several items is saved only once
+    failed_lines: HashSet<u64>,
+
+    // reason of each failed record is saved as the last column of failed csv,
+    // when validation rules are declared
+    save_reasons: bool,
+
+    // epoch seconds when the import starts, for TTL relative to import time
+    import_time: i64,
+
+    // metadata of the import, see add_metadata in config
+    import_id: String,
+    imported_at: String,
+
+    // attributes of the same value in every item, annotations are stripped from names
+    constants: Vec<(String, String)>,
+
+    // format of the date column for TTL
+    ttl_format: Option<TimestampFormat>,
+}
+
+// a write request, together with the record it is built from
+// the record will be saved into the file of failed items if the request fails
+struct PendingWrite {
+    request: WriteRequest,
+
+    // rows of the record as lines of csv, or the json line
+    record: Vec<String>,
+
+    // line of the record in csv, None for json lines
+    line: Option<u64>,
+}
+
+// how an item is built from a row
+struct ItemLayout {
+    // name of the item in schema, for messages
+    name: String,
+
+    // computed attributes of this item only, appended after shared ones
+    templates: Vec<(String, Template)>,
+
+    // columns of the row, then all computed attributes
+    column_names: Vec<String>,
+
+    // path of each column, a single key unless nested mode is enabled
+    column_paths: Vec<Vec<PathSegment>>,
+
+    // columns saved in the item
+    is_included: Vec<bool>,
+
+    // attributes declared in schema, to decide which group attributes are saved
+    attributes: Option<Vec<String>>,
 }
 
 impl Dynamo {
-    pub fn new(config: Config) -> Dynamo {
-        Dynamo {
-            client: DynamoDbClient::new(
-                config.region.parse().expect(
-                    format!(
-                        "{} is not a valid AWS region. Examples of region can be found in help",
-                        config.region.as_str()
-                    )
-                    .as_str(),
-                ),
-            ),
+    pub fn new(config: Config) -> Result<Dynamo> {
+        let now = Utc::now();
+        let create_file = |filename: &str| {
+            File::create(filename)
+                .map(BufWriter::new)
+                .map_err(|error| Error::Io(format!("Cannot create {}. {}", filename, error)))
+        };
+
+        Ok(Dynamo {
+            client: create_client(&config.region)?,
             parser: Parser {
                 allow_set: config.allow_set,
                 allow_null: config.allow_null,
+                empty_policy: config.empty_policy,
+                detect_dynamodb_json: config.detect_dynamodb_json,
+                schema: config.schema.clone(),
             },
-            config: config,
+            failed_writer: create_file(config.input_format.failed_file_name())?,
+            config,
             table_attrs: HashMap::new(),
-            logger: BufWriter::new(File::create(LOG_FILE_NAME).unwrap()),
-            csv_writer: BufWriter::new(File::create(FAILED_CSV_FILE_NAME).unwrap()),
-        }
+            table_keys: Vec::new(),
+            templates: Vec::new(),
+            layouts: Vec::new(),
+            logger: create_file(LOG_FILE_NAME)?,
+            failed_lines: HashSet::new(),
+            save_reasons: false,
+            import_time: now.timestamp(),
+            import_id: Uuid::new_v4().to_string(),
+            imported_at: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            constants: Vec::new(),
+            ttl_format: None,
+        })
     }
 
-    // save all records into dynamoDB (multiple batches)
-    pub async fn save_to_dynamo(&mut self, header: &Vec<String>, rows: &Vec<Vec<String>>) {
-        // preview first record to check if type inference works as expected
-        if self.config.should_preview_record {
-            self.preview_record(header, &rows[0]);
+    // save all csv rows into dynamoDB (multiple batches)
+    // lines are where rows start in csv, to trace failed items
+    pub async fn save_to_dynamo(
+        &mut self,
+        header: &[String],
+        rows: &[Vec<String>],
+        lines: &[u64],
+    ) -> Result<()> {
+        // strip type annotations from header, the types are added to schema
+        // then rename and drop columns
+        let column_names = self
+            .parser
+            .schema
+            .apply_header_annotations(header)
+            .and_then(|column_names| self.parser.schema.apply_mapping(&column_names))
+            .map_err(|error| Error::Config(format!("Invalid header. {}", error)))?;
+        // columns from lookup files are added to the end of header
+        let (lookups, lookup_names) = load_lookups(&self.parser.schema, &column_names)?;
+        let column_names = [column_names, lookup_names].concat();
+        let script = match &self.config.script {
+            Some(filename) => Some(Script::load(filename).map_err(|error| {
+                Error::Config(format!("Invalid script {}. {}", filename, error))
+            })?),
+            None => None,
+        };
+
+        // save header into csv of failed items, annotations are kept
+        self.save_reasons = self
+            .parser
+            .schema
+            .columns
+            .values()
+            .any(|x| x.validate.is_some());
+        self.save_failed_record(&[format_csv_row(header)], FAILED_REASON_COLUMN)?;
+
+        // failed rows are saved as they are in csv, so they can be fixed and retried
+        let original_rows: HashMap<_, _> = lines.iter().copied().zip(rows).collect();
+
+        // rows of different length from header are ignored, then rows are enriched by lookups
+        let mut enriched_rows = Vec::new();
+        let mut invalid_count = 0;
+        let mut dropped_count = 0;
+        'rows: for (row, line) in rows.iter().zip(lines) {
+            if header.len() != row.len() {
+                println!(
+                    "Mismatch between header and row at line {}. Row ignored: {}",
+                    line,
+                    row.join(" | ")
+                );
+                invalid_count += 1;
+                continue;
+            }
+
+            let mut enriched_row: Row = row.iter().cloned().map(Some).collect();
+            for lookup in &lookups {
+                match lookup.find(row) {
+                    Ok(Some(values)) => enriched_row.extend(values),
+                    Ok(None) => {
+                        dropped_count += 1;
+                        continue 'rows;
+                    }
+                    Err(reason) => {
+                        invalid_count += 1;
+                        self.reject_row(*line, row, &reason)?;
+                        continue 'rows;
+                    }
+                }
+            }
+            enriched_rows.push((enriched_row, *line));
+        }
+        if dropped_count > 0 {
+            println!(
+                "{} rows without match in lookup files are dropped",
+                dropped_count
+            );
+        }
+
+        // then rows are transformed by script
+        let (column_names, enriched_rows) = match &script {
+            Some(script) => {
+                let mut script_rows = Vec::new();
+                let mut dropped_count = 0;
+                for (row, line) in enriched_rows {
+                    match script.transform(&column_names, &row) {
+                        Ok(rows) if rows.is_empty() => dropped_count += 1,
+                        Ok(rows) => script_rows.extend(rows.into_iter().map(|x| (x, line))),
+                        Err(reason) => {
+                            invalid_count += 1;
+                            let reason = format!("Script: {}", reason);
+                            self.reject_row(line, original_rows[&line], &reason)?;
+                        }
+                    }
+                }
+                if dropped_count > 0 {
+                    println!("{} rows are dropped by script", dropped_count);
+                }
+                build_rows(column_names, script_rows)
+            }
+            None => (column_names, enriched_rows),
+        };
+
+        // rows violating validation rules are rejected, or abort the import in strict mode
+        let validator = Validator::new(&self.parser.schema, &column_names)
+            .map_err(|error| Error::Config(format!("Invalid validation rule. {}", error)))?;
+        let mut valid_rows = Vec::new();
+        for (row, line) in enriched_rows {
+            if let Err(reason) = validator.validate(&row) {
+                if self.config.strict {
+                    return Err(Error::Csv(format!(
+                        "Invalid row at line {}. {}\nImport aborted in strict mode, nothing has been uploaded",
+                        line, reason
+                    )));
+                }
+                invalid_count += 1;
+                self.reject_row(line, original_rows[&line], &reason)?;
+                continue;
+            }
+            valid_rows.push((row, line));
+        }
+
+        if let Some(rule) = &self.parser.schema.group {
+            check_group_rule(rule, &column_names)
+                .map_err(|error| Error::Config(format!("Invalid group. {}", error)))?;
         }
+        let column_names = self.add_computed_columns(column_names)?;
+        self.layouts = self.get_item_layouts(&column_names)?;
+        self.add_constants(&column_names)?;
+        self.ttl_format = self.get_ttl_format(&column_names)?;
 
         // get table definition (type of primary key/sort key)
-        self.table_attrs = self.get_table_attrs().await;
+        // before grouping, so numeric keys of the same value are grouped together
+        let (table_attrs, table_keys) = self.get_table_attrs().await?;
+        self.table_attrs = table_attrs;
+        self.table_keys = table_keys;
+        self.parser
+            .schema
+            .check_key_types(&self.table_attrs)
+            .map_err(Error::Config)?;
+        self.check_key_columns()?;
+        if self.config.ttl.is_some() {
+            self.check_ttl().await?;
+        }
+
+        // then rows are grouped if required
+        let records = group_rows(
+            self.parser.schema.group.as_ref(),
+            &column_names,
+            &self.parser,
+            &self.table_attrs,
+            valid_rows.iter().map(|(row, line)| (row, *line)).collect(),
+        );
+
+        // preview first record to check if type inference works as expected
+        if self.config.should_preview_record && !records.is_empty() {
+            let requests = self
+                .build_record_requests(&records[0], &column_names)
+                .map_err(|reason| {
+                    Error::Csv(format!(
+                        "Invalid csv: cannot parse the first record. {}",
+                        reason
+                    ))
+                })?;
+            self.preview_requests(&requests)?;
+        }
+
+        println!("Starting to upload records:");
+
+        let mut pending_writes = Vec::new();
+        for record in &records {
+            // rows of a group are saved or failed together, each row of csv only once
+            let text: Vec<_> = record
+                .lines
+                .iter()
+                .unique()
+                .map(|line| format_csv_row(original_rows[line]))
+                .collect();
+
+            match self.build_record_requests(record, &column_names) {
+                Ok(requests) => {
+                    pending_writes.extend(requests.into_iter().map(|request| PendingWrite {
+                        request,
+                        record: text.to_owned(),
+                        line: Some(record.line),
+                    }));
+                }
+                Err(reason) => {
+                    // invalid rows are saved with failed items, so they can be fixed and retried
+                    println!(
+                        "Invalid row at line {} ignored. {}: {}",
+                        record.line,
+                        reason,
+                        original_rows[&record.line].join(" | ")
+                    );
+                    if self.failed_lines.insert(record.line) {
+                        self.save_failed_record(&text, &reason)?;
+                    }
+                }
+            }
+        }
+
+        let success_count = self.all_batch_write(&pending_writes).await?;
+        self.print_summary(
+            success_count,
+            (records.len() + invalid_count) * self.layouts.len(),
+        );
 
-        // save header into csv of failed items
-        self.save_row_to_csv(header);
+        Ok(())
+    }
+
+    // save items which are already in DynamoDB JSON format, skipping type inference
+    // each item comes with its original line, which is saved as is if the item fails
+    // keys of the table are still read, so a batch ends before a key is repeated
+    pub async fn save_items_to_dynamo(
+        &mut self,
+        items: &[(HashMap<String, AttributeValue>, String)],
+    ) -> Result<()> {
+        let (table_attrs, table_keys) = self.get_table_attrs().await?;
+        self.table_attrs = table_attrs;
+        self.table_keys = table_keys;
+
+        let pending_writes: Vec<_> = items
+            .iter()
+            .map(|(item, line)| PendingWrite {
+                request: WriteRequest {
+                    put_request: Some(PutRequest {
+                        item: item.to_owned(),
+                    }),
+                    ..Default::default()
+                },
+                record: vec![line.to_owned()],
+                line: None,
+            })
+            .collect();
+
+        if self.config.should_preview_record {
+            self.preview_requests(&[pending_writes[0].request.to_owned()])?;
+        }
 
         println!("Starting to upload records:");
 
-        let success_count = self.all_batch_write(header, rows).await;
-        let error_rate = 100.0 * (rows.len() - success_count) as f64 / rows.len() as f64;
+        let success_count = self.all_batch_write(&pending_writes).await?;
+        self.print_summary(success_count, items.len());
+
+        Ok(())
+    }
+
+    fn print_summary(&mut self, success_count: usize, total_count: usize) {
+        let error_rate = 100.0 * (total_count - success_count) as f64 / total_count as f64;
 
         println!("All the records have been processed!");
         if self.config.enable_log {
             println!("Logs has been saved to {}", LOG_FILE_NAME);
         }
-        println!("Failed items has been saved to {}", FAILED_CSV_FILE_NAME);
+        println!(
+            "Failed items has been saved to {}",
+            self.config.input_format.failed_file_name()
+        );
         println!(
             "{}/{} items has been saved in DynamoDB. Error rate: {:.2}%",
-            success_count,
-            rows.len(),
-            error_rate
+            success_count, total_count, error_rate
         );
         println!();
     }
 
     // preview record for user to check if type inference works as expected
-    fn preview_record(&mut self, header: &Vec<String>, row: &Vec<String>) {
-        let item = self
-            .build_write_request(header, row, &self.table_attrs)
-            .put_request
-            .expect("Invalid csv: cannot parse the first record")
-            .item;
-        println!(
-            "Preview the first record in DynamoDB Json format: {}",
-            serde_json::to_string(&item).unwrap()
-        );
+    // with fan-out, all items of the record are shown
+    fn preview_requests(&self, requests: &[WriteRequest]) -> Result<()> {
+        let items: Vec<_> = requests
+            .iter()
+            .filter_map(|request| request.put_request.as_ref())
+            .map(|request| serde_json::to_string(&request.item).unwrap())
+            .collect();
 
-        if !read_yes_or_no("Does the record format look correct?", true) {
-            println!("Incorrect format, exiting...");
-            exit(-1);
+        if items.len() == 1 {
+            println!(
+                "Preview the first record in DynamoDB Json format: {}",
+                items[0]
+            );
+        } else {
+            println!("Preview items of the first record in DynamoDB Json format:");
+            for (layout, item) in self.layouts.iter().zip(&items) {
+                println!("{}: {}", layout.name, item);
+            }
+        }
+
+        if !read_yes_or_no("Does the record format look correct?", true)? {
+            return Err(Error::Config("Incorrect format, exiting...".to_string()));
         }
 
         println!();
+        Ok(())
     }
 
-    // split all rows into batches and upload them sequentially
-    async fn all_batch_write(&mut self, header: &Vec<String>, rows: &Vec<Vec<String>>) -> usize {
+    // split all requests into batches and upload them sequentially
+    // DynamoDB rejects a batch with duplicate keys, so a batch ends before a key is repeated
+    // then the later item overwrites the earlier one, as single writes would
+    async fn all_batch_write(&mut self, pending_writes: &[PendingWrite]) -> Result<usize> {
         let mut current_batch = Vec::new();
+        let mut current_keys = HashSet::new();
         let mut success_count = 0;
-        let mut progress_printer = ProgressPrinter::new(rows.len());
+        let mut progress_printer = ProgressPrinter::new(pending_writes.len());
 
-        for (i, row) in rows.iter().enumerate() {
-            current_batch.push(row);
+        for (i, pending_write) in pending_writes.iter().enumerate() {
+            let key = self.get_item_key(&pending_write.request);
+            if !self.table_keys.is_empty() && !current_keys.insert(key.to_owned()) {
+                success_count += self.batch_write(&current_batch).await?;
+                current_batch.clear();
+                current_keys.clear();
+                current_keys.insert(key);
+            }
+            current_batch.push(pending_write);
             progress_printer.update_progress(i + 1);
 
             if current_batch.len() >= self.config.batch_size {
-                success_count += self.batch_write(header, &current_batch).await;
+                success_count += self.batch_write(&current_batch).await?;
                 if self.config.batch_interval > 0 {
                     sleep(Duration::from_millis(self.config.batch_interval));
                 }
                 current_batch.clear();
+                current_keys.clear();
             }
         }
 
-        // if there's still some rows left
+        // if there's still some requests left
         if !current_batch.is_empty() {
-            success_count += self.batch_write(header, &current_batch).await;
+            success_count += self.batch_write(&current_batch).await?;
         }
 
-        success_count
+        Ok(success_count)
     }
 
-    // one batch write, 25 rows at most
-    async fn batch_write(&mut self, header: &Vec<String>, rows: &Vec<&Vec<String>>) -> usize {
-        let mut write_requests = Vec::new();
-        let mut success_count = 0;
+    // values of key attributes, numbers in canonical form, e.g. 1.50 and 1.5 are the same key
+    fn get_item_key(&self, request: &WriteRequest) -> Vec<Option<String>> {
+        let item = request.put_request.as_ref().map(|x| &x.item);
+        self.table_keys
+            .iter()
+            .map(|key| {
+                item.and_then(|x| x.get(key)).map(|attr| match &attr.n {
+                    Some(number) => canonical_number(number).unwrap_or_else(|_| number.to_owned()),
+                    None => attr_to_text(attr),
+                })
+            })
+            .collect()
+    }
 
-        for row in rows {
-            if header.len() != row.len() {
-                println!(
-                    "Mismatch between header and row. Row ignored: {}",
-                    row.join(" | ")
-                );
-            } else {
-                write_requests.push(self.build_write_request(header, row, &self.table_attrs));
-            }
-        }
+    // one batch write, 25 requests at most
+    // a failed batch is not an error, its records are saved with failed items
+    async fn batch_write(&mut self, pending_writes: &[&PendingWrite]) -> Result<usize> {
+        let write_requests: Vec<WriteRequest> = pending_writes
+            .iter()
+            .map(|x| x.request.to_owned())
+            .collect();
+        let mut success_count = 0;
 
         if !write_requests.is_empty() {
             let mut batch_items = HashMap::new();
@@ -154,66 +509,491 @@ impl Dynamo {
 
             match self.client.batch_write_item(input).await {
                 Ok(_) => {
-                    self.log_requests(&write_requests, None);
-                    success_count += rows.len();
+                    self.log_requests(pending_writes, None)?;
+                    success_count += write_requests.len();
                 }
                 Err(error) => {
-                    self.log_requests(&write_requests, Some(error));
-                    for row in rows {
-                        self.save_row_to_csv(row);
+                    let reason = error.to_string();
+                    self.log_requests(pending_writes, Some(error))?;
+                    for pending_write in pending_writes {
+                        // items of the same record may fail in different batches
+                        if let Some(line) = pending_write.line {
+                            if !self.failed_lines.insert(line) {
+                                continue;
+                            }
+                        }
+                        self.save_failed_record(&pending_write.record, &reason)?;
+                    }
+                }
+            }
+        }
+
+        Ok(success_count)
+    }
+
+    // build write requests of all items for given record
+    // attributes collected and aggregated from a group are added to every item, unless excluded
+    fn build_record_requests(
+        &self,
+        record: &Record,
+        column_names: &[String],
+    ) -> Result<Vec<WriteRequest>, String> {
+        if let Some(rule) = &self.parser.schema.group {
+            check_group_values(&self.parser, rule, column_names, &record.rows)?;
+        }
+        let mut requests =
+            self.build_write_requests(record.rows[0], record.line, &self.table_attrs)?;
+
+        if let Some(rule) = &self.parser.schema.group {
+            let group_attrs = build_group_attrs(&self.parser, rule, column_names, &record.rows)?;
+            for (layout, request) in self.layouts.iter().zip(&mut requests) {
+                let item = &mut request.put_request.as_mut().unwrap().item;
+                for (attribute_name, attr) in &group_attrs {
+                    let is_included = match &layout.attributes {
+                        Some(attributes) => attributes.contains(attribute_name),
+                        None => true,
+                    };
+                    if is_included {
+                        item.insert(attribute_name.to_owned(), attr.to_owned());
                     }
                 }
             }
         }
 
-        success_count
+        Ok(requests)
     }
 
-    // build a single write request for given header and row
+    // build write requests of all items for given row
+    // returns error if any item cannot be built, so the row is saved or failed as a whole
+    fn build_write_requests(
+        &self,
+        row: &[Option<String>],
+        line: u64,
+        table_attrs: &HashMap<String, String>,
+    ) -> Result<Vec<WriteRequest>, String> {
+        self.layouts
+            .iter()
+            .map(|layout| {
+                self.build_write_request(layout, row, line, table_attrs)
+                    .map_err(|reason| match self.layouts.len() {
+                        1 => reason,
+                        _ => format!("Item {}: {}", layout.name, reason),
+                    })
+            })
+            .collect()
+    }
+
+    // build a single write request for given item layout and row
+    // returns error if any column cannot be converted to its declared type
+    // constants and metadata are added to every item, regardless of its subset of columns
     fn build_write_request(
         &self,
-        header: &Vec<String>,
-        row: &Vec<String>,
+        layout: &ItemLayout,
+        row: &[Option<String>],
+        line: u64,
         table_attrs: &HashMap<String, String>,
-    ) -> WriteRequest {
-        let mut items = HashMap::new();
+    ) -> Result<WriteRequest, String> {
+        let header = &layout.column_names;
+        let mut nested_item = NestedItem::new();
+
+        // values of computed attributes are added to the end of row, same as header
+        let mut row = row.to_vec();
+        for (attribute_name, template) in self.templates.iter().chain(&layout.templates) {
+            let value = template
+                .render(&row)
+                .map_err(|reason| format!("Attribute {}: {}", attribute_name, reason))?;
+            row.push(Some(value));
+        }
 
         // row must have the same length as header (check before calling this method)
         for (i, column_name) in header.iter().enumerate() {
+            if self.parser.is_skipped(column_name) || !layout.is_included[i] {
+                continue;
+            }
+
+            let attribute = self
+                .parser
+                .build_row_cell_attr(column_name, table_attrs.get(column_name), row[i].to_owned())
+                .map_err(|reason| format!("Column {}: {}", column_name, reason))?;
+            if let Some(attribute) = attribute {
+                nested_item.insert(&layout.column_paths[i], attribute);
+            }
+        }
+
+        let mut items = nested_item.into_item();
+
+        if let Some(ttl) = &self.config.ttl {
+            if let Some(expiry_time) = self.get_expiry_time(header, &row)? {
+                let ttl_attr = AttributeValue {
+                    n: Some(expiry_time.to_string()),
+                    ..Default::default()
+                };
+                items.insert(ttl.attribute_name.to_owned(), ttl_attr);
+            }
+        }
+
+        for (attribute_name, value) in &self.constants {
             let attribute = self
                 .parser
-                .build_attr(table_attrs.get(column_name), row[i].to_string());
-            if self.config.allow_null || attribute.null.is_none() {
-                items.insert(column_name.to_owned(), attribute);
+                .build_cell_attr(
+                    attribute_name,
+                    table_attrs.get(attribute_name),
+                    value.to_owned(),
+                )
+                .map_err(|reason| format!("Attribute {}: {}", attribute_name, reason))?;
+            if let Some(attribute) = attribute {
+                items.insert(attribute_name.to_owned(), attribute);
             }
         }
 
-        WriteRequest {
+        if self.config.add_metadata {
+            items.extend(self.get_metadata_attrs(line));
+        }
+
+        // e.g. an empty or null cell of key column
+        if let Some(key) = self.table_keys.iter().find(|x| !items.contains_key(*x)) {
+            return Err(format!("Key attribute {} is missing", key));
+        }
+
+        Ok(WriteRequest {
             put_request: Some(PutRequest { item: items }),
             ..Default::default()
+        })
+    }
+
+    // computed attributes are added to the end of header, so they are built like columns
+    fn add_computed_columns(&mut self, mut column_names: Vec<String>) -> Result<Vec<String>> {
+        let schema = &self.parser.schema;
+        let mut attribute_names: Vec<_> = schema.computed.keys().collect();
+        attribute_names.sort();
+
+        for attribute_name in attribute_names {
+            if column_names.contains(attribute_name) {
+                return Err(Error::Config(format!(
+                    "Invalid computed attribute. {} is already a column",
+                    attribute_name
+                )));
+            }
+
+            let template = Template::new(&schema.computed[attribute_name], &column_names, schema)
+                .map_err(|error| {
+                Error::Config(format!(
+                    "Invalid computed attribute {}. {}",
+                    attribute_name, error
+                ))
+            })?;
+            self.templates.push((attribute_name.to_owned(), template));
         }
+
+        column_names.extend(self.templates.iter().map(|x| x.0.to_owned()));
+        Ok(column_names)
+    }
+
+    // types of constants are inferred like cells, unless annotated in names
+    // all attribute names must be unique, as constants are added to every item
+    fn add_constants(&mut self, column_names: &[String]) -> Result<()> {
+        let names: Vec<_> = self
+            .config
+            .constants
+            .iter()
+            .map(|x| x.0.to_owned())
+            .collect();
+        let names = self
+            .parser
+            .schema
+            .apply_header_annotations(&names)
+            .map_err(|error| Error::Config(format!("Invalid constant. {}", error)))?;
+
+        let mut attribute_names: Vec<_> = column_names
+            .iter()
+            .chain(
+                self.layouts
+                    .iter()
+                    .flat_map(|x| x.templates.iter().map(|y| &y.0)),
+            )
+            .map(|x| x.to_owned())
+            .collect();
+        if let Some(rule) = &self.parser.schema.group {
+            attribute_names.extend(rule.collect.keys().chain(rule.aggregate.keys()).cloned());
+        }
+        if self.config.add_metadata {
+            attribute_names.extend(METADATA_ATTRIBUTES.iter().map(|x| x.to_string()));
+        }
+
+        for (name, (_, value)) in names.into_iter().zip(&self.config.constants) {
+            if attribute_names.contains(&name) {
+                return Err(Error::Config(format!(
+                    "Invalid constant. {} is already an attribute",
+                    name
+                )));
+            }
+            attribute_names.push(name.to_owned());
+            self.constants.push((name, value.to_owned()));
+        }
+
+        if self.config.add_metadata {
+            println!("Import id: {}", self.import_id);
+        }
+
+        Ok(())
+    }
+
+    // attributes to trace where an item comes from
+    fn get_metadata_attrs(&self, line: u64) -> Vec<(String, AttributeValue)> {
+        let string_attr = |value: &str| AttributeValue {
+            s: Some(value.to_string()),
+            ..Default::default()
+        };
+        let values = vec![
+            string_attr(&self.import_id),
+            string_attr(&self.imported_at),
+            string_attr(&self.config.filename),
+            AttributeValue {
+                n: Some(line.to_string()),
+                ..Default::default()
+            },
+        ];
+
+        METADATA_ATTRIBUTES
+            .iter()
+            .map(|x| x.to_string())
+            .zip(values)
+            .collect()
+    }
+
+    // each item has its own computed attributes and subset of columns
+    fn get_item_layouts(&self, column_names: &[String]) -> Result<Vec<ItemLayout>> {
+        let schema = &self.parser.schema;
+
+        // collected and aggregated columns are replaced by group attributes
+        let (grouped_columns, group_attributes) = match &schema.group {
+            Some(rule) => (
+                grouped_columns(rule),
+                rule.collect.keys().chain(rule.aggregate.keys()).collect(),
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+
+        let definitions = if schema.items.is_empty() {
+            vec![ItemDefinition::default()]
+        } else {
+            schema.items.clone()
+        };
+
+        let mut layouts = Vec::new();
+        for (i, definition) in definitions.into_iter().enumerate() {
+            let name = definition.name.unwrap_or_else(|| format!("#{}", i + 1));
+            let invalid_item =
+                |error: String| Error::Config(format!("Invalid item {}. {}", name, error));
+
+            // computed attributes can refer to columns and shared computed attributes
+            let mut layout_names = column_names.to_vec();
+            let mut templates = Vec::new();
+            let mut attribute_names: Vec<_> = definition.computed.keys().collect();
+            attribute_names.sort();
+            for attribute_name in attribute_names {
+                if layout_names.contains(attribute_name)
+                    || group_attributes.contains(&attribute_name)
+                {
+                    return Err(invalid_item(format!(
+                        "{} is already a column",
+                        attribute_name
+                    )));
+                }
+                let template =
+                    Template::new(&definition.computed[attribute_name], column_names, schema)
+                        .map_err(|error| {
+                            invalid_item(format!("Attribute {}: {}", attribute_name, error))
+                        })?;
+                templates.push((attribute_name.to_owned(), template));
+                layout_names.push(attribute_name.to_owned());
+            }
+
+            // computed attributes of the item are always included
+            let is_included: Vec<_> = match &definition.attributes {
+                None => layout_names
+                    .iter()
+                    .map(|x| !grouped_columns.contains(&x))
+                    .collect(),
+                Some(attributes) => {
+                    if let Some(x) = attributes
+                        .iter()
+                        .find(|x| !column_names.contains(x) && !group_attributes.contains(x))
+                    {
+                        return Err(invalid_item(format!("Attribute {} is not found", x)));
+                    }
+                    layout_names
+                        .iter()
+                        .enumerate()
+                        .map(|(j, x)| j >= column_names.len() || attributes.contains(x))
+                        .collect()
+                }
+            };
+
+            layouts.push(ItemLayout {
+                column_paths: self.get_column_paths(&layout_names, &is_included)?,
+                name,
+                templates,
+                column_names: layout_names,
+                is_included,
+                attributes: definition.attributes,
+            });
+        }
+
+        Ok(layouts)
+    }
+
+    // parse headers into paths of attributes
+    // without nested mode, each header is used as attribute name as is
+    fn get_column_paths(
+        &self,
+        column_names: &[String],
+        is_included: &[bool],
+    ) -> Result<Vec<Vec<PathSegment>>> {
+        if !self.config.nested {
+            return Ok(column_names
+                .iter()
+                .map(|x| vec![PathSegment::Key(x.to_owned())])
+                .collect());
+        }
+        let invalid_header = |error: String| Error::Config(format!("Invalid header. {}", error));
+
+        let column_paths: Vec<_> = column_names
+            .iter()
+            .map(|x| parse_path(x))
+            .collect::<Result<_, _>>()
+            .map_err(invalid_header)?;
+
+        // skipped columns never conflict, as they are not saved
+        let (names, paths): (Vec<_>, Vec<_>) = column_names
+            .iter()
+            .zip(&column_paths)
+            .zip(is_included)
+            .filter(|((name, _), is_included)| **is_included && !self.parser.is_skipped(name))
+            .map(|(x, _)| x)
+            .unzip();
+        check_paths(&names, &paths).map_err(invalid_header)?;
+
+        Ok(column_paths)
+    }
+
+    // epoch seconds when the item expires
+    // None if the date column is empty or null, so the item never expires
+    fn get_expiry_time(
+        &self,
+        header: &[String],
+        row: &[Option<String>],
+    ) -> Result<Option<i64>, String> {
+        match &self.config.ttl.as_ref().unwrap().source {
+            TtlSource::Offset(seconds) => Ok(Some(self.import_time + seconds)),
+            TtlSource::Column(column_name, _) => {
+                let i = header.iter().position(|x| x == column_name).unwrap();
+                let text = row[i].as_deref().unwrap_or_default().trim();
+                if text.is_empty() || self.parser.schema.column_tokens(column_name).is_null(text) {
+                    return Ok(None);
+                }
+
+                let ttl_format = self.ttl_format.as_ref().unwrap();
+                ttl_format
+                    .parse(text)
+                    .map(|x| Some(x.timestamp()))
+                    .map_err(|reason| format!("TTL: {}", reason))
+            }
+        }
+    }
+
+    // format of the date column for TTL, the column must be in header
+    // if format is not given, timestamp option of the column in schema is used
+    fn get_ttl_format(&self, column_names: &[String]) -> Result<Option<TimestampFormat>> {
+        let ttl = match &self.config.ttl {
+            Some(ttl) => ttl,
+            None => return Ok(None),
+        };
+
+        match &ttl.source {
+            TtlSource::Offset(_) => Ok(None),
+            TtlSource::Column(column_name, format) => {
+                if !column_names.contains(column_name) {
+                    return Err(Error::Config(format!(
+                        "Invalid TTL. Column {} is not found in csv",
+                        column_name
+                    )));
+                }
+
+                let schema_format = self
+                    .parser
+                    .schema
+                    .column(column_name)
+                    .and_then(|x| x.timestamp.clone());
+                Ok(Some(match (format, schema_format) {
+                    (None, Some(schema_format)) => schema_format,
+                    (format, _) => TimestampFormat {
+                        format: format.to_owned().unwrap_or_else(|| "rfc3339".to_string()),
+                        timezone: SourceTimezone::default(),
+                        output: TimestampOutput::Epoch,
+                    },
+                }))
+            }
+        }
+    }
+
+    // TTL attribute should be the one configured on the table
+    async fn check_ttl(&self) -> Result<()> {
+        let ttl_attribute = &self.config.ttl.as_ref().unwrap().attribute_name;
+        let input = DescribeTimeToLiveInput {
+            table_name: self.config.table_name.to_owned(),
+        };
+
+        match self.client.describe_time_to_live(input).await {
+            Ok(output) => {
+                let description = output.time_to_live_description.unwrap_or_default();
+                let status = description.time_to_live_status.unwrap_or_default();
+                let table_attribute = description.attribute_name.unwrap_or_default();
+
+                if status != "ENABLED" && status != "ENABLING" {
+                    println!(
+                        "Warning: TTL is disabled on table {}. Items will not expire until it's enabled",
+                        self.config.table_name
+                    );
+                } else if table_attribute != *ttl_attribute {
+                    return Err(Error::Config(format!(
+                        "TTL attribute of table {} is {}, but {} is given. Items would never expire",
+                        self.config.table_name, table_attribute, ttl_attribute
+                    )));
+                }
+            }
+            Err(error) => {
+                println!(
+                    "Warning: cannot read TTL of table: {}. {}",
+                    self.config.table_name, error
+                );
+            }
+        }
+
+        Ok(())
     }
 
     // get attribute definition of the target table
     // we can only get type of primary key / sort key
-    async fn get_table_attrs(&self) -> HashMap<String, String> {
+    // returns type of attributes in table definition (including indexes), and key attributes of table
+    async fn get_table_attrs(&self) -> Result<(HashMap<String, String>, Vec<String>)> {
         println!("Reading DynamoDB table definition...");
 
         let mut table_attrs = HashMap::new();
+        let mut table_keys = Vec::new();
         let describe_table_input = DescribeTableInput {
             table_name: self.config.table_name.to_owned(),
         };
 
         match self.client.describe_table(describe_table_input).await {
             Ok(table_info) => {
-                let attrs = table_info
-                    .table
-                    .unwrap_or_default()
-                    .attribute_definitions
-                    .unwrap_or_default();
-                for attr in attrs {
+                let table = table_info.table.unwrap_or_default();
+                for attr in table.attribute_definitions.unwrap_or_default() {
                     table_attrs.insert(attr.attribute_name, attr.attribute_type);
                 }
+                for key in table.key_schema.unwrap_or_default() {
+                    table_keys.push(key.attribute_name);
+                }
                 println!(
                     "{} table definition: {}",
                     self.config.table_name,
@@ -221,30 +1001,56 @@ impl Dynamo {
                 );
             }
             Err(error) => {
-                println!(
+                return Err(Error::Aws(format!(
                     "Cannot read description of table: {}. {}",
                     self.config.table_name, error
-                );
+                )));
             }
         }
-        table_attrs
+        Ok((table_attrs, table_keys))
+    }
+
+    // every key attribute of the table must come from a column which is saved as is
+    fn check_key_columns(&self) -> Result<()> {
+        for layout in &self.layouts {
+            for key in &self.table_keys {
+                let is_present = (0..layout.column_names.len()).any(|i| {
+                    layout.is_included[i]
+                        && !self.parser.is_skipped(&layout.column_names[i])
+                        && layout.column_paths[i] == [PathSegment::Key(key.to_owned())]
+                });
+                if !is_present {
+                    let location = match self.layouts.len() {
+                        1 => "columns after mapping".to_string(),
+                        _ => format!("item {}", layout.name),
+                    };
+                    return Err(Error::Config(format!(
+                        "Key attribute {} of table {} is not found in {}",
+                        key, self.config.table_name, location
+                    )));
+                }
+            }
+        }
+
+        Ok(())
     }
 
     // save a batch of requests to logs
     fn log_requests(
         &mut self,
-        requests: &Vec<WriteRequest>,
+        pending_writes: &[&PendingWrite],
         error: Option<RusotoError<BatchWriteItemError>>,
-    ) {
+    ) -> Result<()> {
         if self.config.enable_log {
             let request_result = match error {
                 None => "Success",
                 _ => "Failure",
             };
 
-            for request in requests {
+            for pending_write in pending_writes {
                 // convert request hashmap to vector then sort by key
-                let mut v: Vec<_> = request
+                let mut v: Vec<_> = pending_write
+                    .request
                     .put_request
                     .clone()
                     .unwrap()
@@ -252,33 +1058,80 @@ impl Dynamo {
                     .into_iter()
                     .collect();
                 v.sort_by(|x, y| x.0.cmp(&y.0));
+
+                // line of csv, to trace items back to their record
+                let line = pending_write
+                    .line
+                    .map(|x| format!(" (line {})", x))
+                    .unwrap_or_default();
                 writeln!(
                     self.logger,
-                    "{}: {}",
+                    "{}{}: {}",
                     request_result,
+                    line,
                     serde_json::to_string(&v).unwrap()
                 )
-                .expect("Error: cannot save logs.");
+                .map_err(cannot_save_logs)?;
             }
 
-            if error.is_some() {
-                writeln!(self.logger, "Error message: {}", error.unwrap())
-                    .expect("Error: cannot save logs.");
+            if let Some(error) = error {
+                writeln!(self.logger, "Error message: {}", error).map_err(cannot_save_logs)?;
             }
 
-            writeln!(self.logger, "=====").unwrap_or_default();
+            writeln!(self.logger, "=====").map_err(cannot_save_logs)?;
         }
+
+        Ok(())
     }
 
-    // save a row to csv of failed items
-    // columns in the row will always be quoted
-    fn save_row_to_csv(&mut self, row: &Vec<String>) {
-        let mut columns = Vec::new();
-        for column in row {
-            // escape quotes, then add a pair of quotes at outermost layer
-            columns.push(format!("\"{}\"", column.replace("\"", "\"\"")));
+    // a row which cannot be imported is saved with failed items, only once for rows built by script
+    fn reject_row(&mut self, line: u64, row: &[String], reason: &str) -> Result<()> {
+        println!(
+            "Invalid row at line {} ignored. {}: {}",
+            line,
+            reason,
+            row.join(" | ")
+        );
+        if self.failed_lines.insert(line) {
+            self.save_failed_record(&[format_csv_row(row)], reason)?;
         }
-        writeln!(self.csv_writer, "{}", columns.join(","))
-            .expect("Error: cannot save failed items to csv.");
+
+        Ok(())
+    }
+
+    // save a record (csv rows or json line) to the file of failed items
+    fn save_failed_record(&mut self, record: &[String], reason: &str) -> Result<()> {
+        for line in record {
+            if self.save_reasons {
+                let reason = format_csv_row(&[reason.to_string()]);
+                writeln!(self.failed_writer, "{},{}", line, reason)
+            } else {
+                writeln!(self.failed_writer, "{}", line)
+            }
+            .map_err(|error| {
+                Error::Io(format!(
+                    "Cannot save failed items to {}. {}",
+                    self.config.input_format.failed_file_name(),
+                    error
+                ))
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+fn cannot_save_logs(error: io::Error) -> Error {
+    Error::Io(format!("Cannot save logs to {}. {}", LOG_FILE_NAME, error))
+}
+
+// create a DynamoDB client for the given region
+pub fn create_client(region: &str) -> Result<DynamoDbClient> {
+    match region.parse() {
+        Ok(region) => Ok(DynamoDbClient::new(region)),
+        Err(_) => Err(Error::Config(format!(
+            "{} is not a valid AWS region. Examples of region can be found in help",
+            region
+        ))),
     }
 }
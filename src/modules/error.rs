@@ -0,0 +1,44 @@
+use std::fmt;
+
+// a problem which stops the program, each kind exits with its own code
+// problems of a single row are not errors, the row is skipped with a reason
+#[derive(Debug)]
+pub enum Error {
+    // invalid options, schema, header or input of prompts
+    Config(String),
+
+    // csv or json lines which cannot be read
+    Csv(String),
+
+    // requests to DynamoDB
+    Aws(String),
+
+    // files which cannot be read or written
+    Io(String),
+}
+
+// errors of a row are still strings, e.g. Result<WriteRequest, String>
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => 2,
+            Error::Csv(_) => 3,
+            Error::Aws(_) => 4,
+            Error::Io(_) => 5,
+        }
+    }
+}
+
+// messages already tell what is wrong, the kind is told by exit code
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(message)
+            | Error::Csv(message)
+            | Error::Aws(message)
+            | Error::Io(message) => write!(f, "{}", message),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use super::config::QueryConfig;
+use super::dynamo::create_client;
+use super::error::{Error, Result};
+use super::parser::{attr_to_dynamodb_json, attr_to_text, attr_type};
+use super::schema::{BinaryEncoding, ColumnType};
+use super::utility::format_csv_row;
+use rusoto_dynamodb::{AttributeValue, DynamoDb, DynamoDbClient, QueryInput};
+use serde_json::to_string;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+pub struct Exporter {
+    client: DynamoDbClient,
+    config: QueryConfig,
+}
+
+impl Exporter {
+    pub fn new(config: QueryConfig) -> Result<Exporter> {
+        Ok(Exporter {
+            client: create_client(&config.region)?,
+            config,
+        })
+    }
+
+    // run the query page by page, then save all items into csv
+    pub async fn query_to_csv(&self) -> Result<()> {
+        println!("Querying {}...", self.config.table_name);
+
+        let items = self.query_all().await?;
+        if items.is_empty() {
+            println!("No items found, exiting...");
+            return Ok(());
+        }
+
+        self.save_items_to_csv(&items)?;
+
+        println!(
+            "{} items has been saved to {}",
+            items.len(),
+            self.config.output_filename
+        );
+        println!();
+
+        Ok(())
+    }
+
+    // follow LastEvaluatedKey until all pages have been read
+    async fn query_all(&self) -> Result<Vec<HashMap<String, AttributeValue>>> {
+        let mut items = Vec::new();
+        let mut exclusive_start_key = None;
+
+        loop {
+            let input = QueryInput {
+                table_name: self.config.table_name.to_owned(),
+                index_name: self.config.index_name.to_owned(),
+                key_condition_expression: Some(self.config.key_condition.to_owned()),
+                filter_expression: self.config.filter_expression.to_owned(),
+                expression_attribute_values: self.config.attribute_values.to_owned(),
+                expression_attribute_names: self.config.attribute_names.to_owned(),
+                exclusive_start_key,
+                ..Default::default()
+            };
+
+            match self.client.query(input).await {
+                Ok(output) => {
+                    items.extend(output.items.unwrap_or_default());
+                    println!("{} items fetched", items.len());
+
+                    exclusive_start_key = output.last_evaluated_key;
+                    if exclusive_start_key.is_none() {
+                        break;
+                    }
+                }
+                Err(error) => {
+                    return Err(Error::Aws(format!(
+                        "Cannot query table: {}. {}",
+                        self.config.table_name, error
+                    )));
+                }
+            }
+        }
+
+        Ok(items)
+    }
+
+    // header is the union of all attribute names, since items can have different attributes
+    // missing attributes are saved as empty cells, which are left out when imported with --empty omit
+    // cells of columns which cannot be annotated are DynamoDB JSON, detected with --dynamodb-json
+    fn save_items_to_csv(&self, items: &[HashMap<String, AttributeValue>]) -> Result<()> {
+        let cannot_save = |error: std::io::Error| {
+            Error::Io(format!(
+                "Cannot save items to {}. {}",
+                self.config.output_filename, error
+            ))
+        };
+        let header: Vec<String> = items
+            .iter()
+            .flat_map(|item| item.keys().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut csv_writer =
+            BufWriter::new(File::create(&self.config.output_filename).map_err(cannot_save)?);
+        let annotations: Vec<_> = header
+            .iter()
+            .map(|column_name| annotate_column(column_name, items))
+            .collect();
+        let annotated_header: Vec<_> = header
+            .iter()
+            .zip(&annotations)
+            .map(|(column_name, annotation)| match annotation {
+                Some(annotation) => format!("{}:{}", column_name, annotation),
+                None => column_name.to_owned(),
+            })
+            .collect();
+        writeln!(csv_writer, "{}", format_csv_row(&annotated_header)).map_err(cannot_save)?;
+
+        for item in items {
+            let row: Vec<String> = header
+                .iter()
+                .zip(&annotations)
+                .map(
+                    |(column_name, annotation)| match (item.get(column_name), annotation) {
+                        (Some(attr), Some(_)) => attr_to_text(attr),
+                        (Some(attr), None) => to_string(&attr_to_dynamodb_json(attr)).unwrap(),
+                        (None, _) => String::new(),
+                    },
+                )
+                .collect();
+            writeln!(csv_writer, "{}", format_csv_row(&row)).map_err(cannot_save)?;
+        }
+
+        csv_writer.flush().map_err(cannot_save)
+    }
+}
+
+// header annotation of the type shared by all items, e.g. S for id:S or B:base64 for blob:B:base64
+// so strings like "123" or "true" are not inferred as other types when imported again
+// None if the text of cells would lose the type, so the column is saved as DynamoDB JSON:
+// different types across items, lists and maps (binaries and sets nested in them),
+// and empty strings or binaries, which are the same as missing attributes
+fn annotate_column(column_name: &str, items: &[HashMap<String, AttributeValue>]) -> Option<String> {
+    let attrs: Vec<_> = items
+        .iter()
+        .filter_map(|item| item.get(column_name))
+        .collect();
+    let types: HashSet<_> = attrs.iter().copied().map(attr_type).collect();
+    let is_empty = |attr: &&AttributeValue| {
+        attr.s.as_ref().is_some_and(|x| x.is_empty())
+            || attr.b.as_ref().is_some_and(|x| x.is_empty())
+    };
+    if types.len() != 1 || attrs.iter().any(is_empty) {
+        return None;
+    }
+
+    match types.into_iter().next().unwrap() {
+        ColumnType::L | ColumnType::M => None,
+        column_type @ ColumnType::B | column_type @ ColumnType::BS => {
+            Some(format!("{}:{}", column_type, BinaryEncoding::Base64))
+        }
+        column_type => Some(column_type.to_string()),
+    }
+}
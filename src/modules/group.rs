@@ -0,0 +1,536 @@
+use super::number::{canonical_number, check_number, compare_numbers, sum_numbers};
+use super::parser::{build_list_attr, build_map_attr, Parser};
+use super::schema::{AggregateFunction, AggregateRule, CollectRule, ColumnType, GroupRule, Tokens};
+use super::utility::Row;
+use itertools::Itertools;
+use rusoto_dynamodb::AttributeValue;
+use std::collections::HashMap;
+
+// rows built into the same item, with the line where the first row starts
+pub struct Record<'a> {
+    pub rows: Vec<&'a Row>,
+    pub line: u64,
+
+    // line of each row, rows built by script may share a line
+    pub lines: Vec<u64>,
+}
+
+// each row is a record, unless rows are grouped by key columns
+// groups are in the order of their first row
+// key columns of type N (declared or key attribute of the table) are compared by value
+// e.g. 1 and 01.0 are the same group, as they are the same number in DynamoDB
+pub fn group_rows<'a>(
+    rule: Option<&GroupRule>,
+    column_names: &[String],
+    parser: &Parser,
+    table_attrs: &HashMap<String, String>,
+    rows: Vec<(&'a Row, u64)>,
+) -> Vec<Record<'a>> {
+    let rule = match rule {
+        Some(rule) => rule,
+        None => {
+            return rows
+                .into_iter()
+                .map(|(row, line)| Record {
+                    rows: vec![row],
+                    line,
+                    lines: vec![line],
+                })
+                .collect()
+        }
+    };
+
+    let key_indexes: Vec<_> = rule
+        .by
+        .iter()
+        .map(|x| column_names.iter().position(|y| x == y).unwrap())
+        .collect();
+    let is_number: Vec<_> = rule
+        .by
+        .iter()
+        .map(|x| {
+            parser.schema.column_type(x) == Some(ColumnType::N)
+                || table_attrs.get(x).is_some_and(|x| x == "N")
+        })
+        .collect();
+    let mut records: Vec<Record> = Vec::new();
+    let mut record_indexes: HashMap<Vec<Option<String>>, usize> = HashMap::new();
+
+    for (row, line) in rows {
+        // invalid numbers are kept as they are, the row is rejected when its item is built
+        let key: Vec<_> = key_indexes
+            .iter()
+            .zip(&is_number)
+            .map(|(i, is_number)| match &row[*i] {
+                Some(text) if *is_number => canonical_number(text).ok().or_else(|| row[*i].clone()),
+                cell => cell.to_owned(),
+            })
+            .collect();
+        match record_indexes.get(&key) {
+            Some(i) => {
+                records[*i].rows.push(row);
+                records[*i].lines.push(line);
+            }
+            None => {
+                record_indexes.insert(key, records.len());
+                records.push(Record {
+                    rows: vec![row],
+                    line,
+                    lines: vec![line],
+                });
+            }
+        }
+    }
+
+    records
+}
+
+// every column in the rule must be in csv (after mapping)
+pub fn check_group_rule(rule: &GroupRule, column_names: &[String]) -> Result<(), String> {
+    let check_column = |column_name: &String| {
+        if column_names.contains(column_name) {
+            Ok(())
+        } else {
+            Err(format!("Column {} is not found in csv", column_name))
+        }
+    };
+
+    if rule.by.is_empty() {
+        return Err("Group must have at least one key column".to_string());
+    }
+    rule.by.iter().try_for_each(check_column)?;
+
+    for (attribute_name, collect) in &rule.collect {
+        collect.columns.iter().try_for_each(check_column)?;
+        match collect.collect_type {
+            None | Some(ColumnType::L) if !collect.columns.is_empty() => {}
+            Some(ColumnType::SS) | Some(ColumnType::NS) | Some(ColumnType::BS)
+                if collect.columns.len() == 1 => {}
+            _ => {
+                return Err(format!(
+                "Attribute {} should collect columns into L, or a single column into SS, NS or BS",
+                attribute_name
+            ))
+            }
+        }
+    }
+
+    for (attribute_name, aggregate) in &rule.aggregate {
+        match (&aggregate.column, aggregate.function) {
+            (Some(column_name), _) => check_column(column_name)?,
+            (None, AggregateFunction::Count) => {}
+            (None, function) => {
+                return Err(format!(
+                    "Attribute {} requires a column for {}",
+                    attribute_name,
+                    format!("{:?}", function).to_lowercase()
+                ))
+            }
+        }
+    }
+
+    // a group attribute can only replace a column which is collected or aggregated
+    let grouped_columns = grouped_columns(rule);
+    for attribute_name in rule.collect.keys().chain(rule.aggregate.keys()) {
+        if column_names.contains(attribute_name) && !grouped_columns.contains(&attribute_name) {
+            return Err(format!("Attribute {} is already a column", attribute_name));
+        }
+    }
+
+    Ok(())
+}
+
+// other columns are taken from the first row, so they must have the same value in all rows
+// otherwise the values of later rows would be lost, and the group fails
+// skipped columns are not saved, so they can be different
+pub fn check_group_values(
+    parser: &Parser,
+    rule: &GroupRule,
+    column_names: &[String],
+    rows: &[&Row],
+) -> Result<(), String> {
+    let grouped_columns = grouped_columns(rule);
+
+    for (i, column_name) in column_names.iter().enumerate().take(rows[0].len()) {
+        if rule.by.contains(column_name)
+            || grouped_columns.contains(&column_name)
+            || parser.is_skipped(column_name)
+        {
+            continue;
+        }
+
+        let values: Vec<_> = rows.iter().map(|row| &row[i]).unique().collect();
+        if values.len() > 1 {
+            return Err(format!(
+                "Column {} has different values in rows of the group: {}",
+                column_name,
+                values
+                    .iter()
+                    .map(|x| x.as_deref().unwrap_or("(missing)"))
+                    .join(" | ")
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// columns which are collected or aggregated, so they are not saved as attributes of the item
+pub fn grouped_columns(rule: &GroupRule) -> Vec<&String> {
+    rule.collect
+        .values()
+        .flat_map(|x| &x.columns)
+        .chain(rule.aggregate.values().filter_map(|x| x.column.as_ref()))
+        .filter(|x| !rule.by.contains(x))
+        .collect()
+}
+
+// attributes collected and aggregated from all rows of a group
+pub fn build_group_attrs(
+    parser: &Parser,
+    rule: &GroupRule,
+    column_names: &[String],
+    rows: &[&Row],
+) -> Result<HashMap<String, AttributeValue>, String> {
+    let mut attrs = HashMap::new();
+
+    for (attribute_name, collect) in &rule.collect {
+        let attr = build_collect_attr(parser, collect, column_names, rows)
+            .map_err(|reason| format!("Attribute {}: {}", attribute_name, reason))?;
+        if let Some(attr) = attr {
+            attrs.insert(attribute_name.to_owned(), attr);
+        }
+    }
+
+    for (attribute_name, aggregate) in &rule.aggregate {
+        let result = match &aggregate.column {
+            Some(column_name) => aggregate_values(
+                aggregate,
+                &parser.schema.column_tokens(column_name),
+                column_values(column_names, rows, column_name),
+            ),
+            None => Ok(Some(rows.len().to_string())),
+        };
+        let result =
+            result.map_err(|reason| format!("Attribute {}: {}", attribute_name, reason))?;
+        if let Some(number) = result {
+            attrs.insert(
+                attribute_name.to_owned(),
+                AttributeValue {
+                    n: Some(number),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    Ok(attrs)
+}
+
+// a list of maps (one per row), or a set of unique values
+fn build_collect_attr(
+    parser: &Parser,
+    collect: &CollectRule,
+    column_names: &[String],
+    rows: &[&Row],
+) -> Result<Option<AttributeValue>, String> {
+    match collect.collect_type {
+        None | Some(ColumnType::L) => {
+            let columns: Vec<_> = collect
+                .columns
+                .iter()
+                .map(|x| (x, column_values(column_names, rows, x)))
+                .collect();
+            let mut list = Vec::new();
+
+            for i in 0..rows.len() {
+                let mut map = HashMap::new();
+                for (column_name, column_values) in &columns {
+                    let attr = parser
+                        .build_row_cell_attr(column_name, None, column_values[i].map(String::from))
+                        .map_err(|reason| format!("Column {}: {}", column_name, reason))?;
+                    if let Some(attr) = attr {
+                        map.insert(column_name.to_string(), attr);
+                    }
+                }
+                list.push(build_map_attr(map));
+            }
+
+            Ok(Some(build_list_attr(list)))
+        }
+        Some(set_type) => {
+            let column_name = &collect.columns[0];
+            let values = column_values(column_names, rows, column_name);
+            parser.build_set_attr(
+                column_name,
+                set_type,
+                values.into_iter().flatten().map(String::from).collect(),
+            )
+        }
+    }
+}
+
+// empty, null and missing values are ignored
+// None if there's no value for min or max
+fn aggregate_values(
+    aggregate: &AggregateRule,
+    tokens: &Tokens,
+    values: Vec<Option<&str>>,
+) -> Result<Option<String>, String> {
+    let values: Vec<_> = values
+        .into_iter()
+        .flatten()
+        .filter(|x| !x.is_empty() && !tokens.is_null(x))
+        .collect();
+    let check_numbers = || {
+        values
+            .iter()
+            .try_for_each(|x| check_number(x).map_err(|error| format!("{} {}", x, error)))
+    };
+
+    match aggregate.function {
+        AggregateFunction::Count => Ok(Some(values.len().to_string())),
+        AggregateFunction::Sum => {
+            check_numbers()?;
+            sum_numbers(&values)
+                .map(Some)
+                .map_err(|error| format!("sum {}", error))
+        }
+        AggregateFunction::Min => {
+            check_numbers()?;
+            Ok(values
+                .iter()
+                .min_by(|a, b| compare_numbers(a, b).unwrap())
+                .map(|x| x.to_string()))
+        }
+        AggregateFunction::Max => {
+            check_numbers()?;
+            Ok(values
+                .iter()
+                .max_by(|a, b| compare_numbers(a, b).unwrap())
+                .map(|x| x.to_string()))
+        }
+    }
+}
+
+fn column_values<'a>(
+    column_names: &[String],
+    rows: &[&'a Row],
+    column_name: &str,
+) -> Vec<Option<&'a str>> {
+    let i = column_names.iter().position(|x| x == column_name).unwrap();
+    rows.iter().map(|row| row[i].as_deref()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::from_str;
+
+    fn parser(schema: &str) -> Parser {
+        Parser {
+            allow_set: false,
+            allow_null: false,
+            empty_policy: None,
+            detect_dynamodb_json: false,
+            schema: from_str(schema).unwrap(),
+        }
+    }
+
+    fn rows(rows: &[&[&str]]) -> Vec<Row> {
+        rows.iter()
+            .map(|row| row.iter().map(|x| Some(x.to_string())).collect())
+            .collect()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|x| x.to_string()).collect()
+    }
+
+    fn number(text: &str) -> AttributeValue {
+        AttributeValue {
+            n: Some(text.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn group_lines(records: &[Record]) -> Vec<Vec<u64>> {
+        records.iter().map(|x| x.lines.to_owned()).collect()
+    }
+
+    #[test]
+    fn each_row_is_a_record_without_rule() {
+        let parser = parser("{}");
+        let rows = rows(&[&["1", "a"], &["1", "b"]]);
+        let records = group_rows(
+            None,
+            &names(&["id", "x"]),
+            &parser,
+            &HashMap::new(),
+            rows.iter().zip([2, 3]).collect(),
+        );
+        assert_eq!(group_lines(&records), vec![vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn rows_are_grouped_in_order_of_first_row() {
+        let rule: GroupRule = from_str(r#"{"by": ["id", "sk"]}"#).unwrap();
+        let parser = parser(r#"{"columns": {"sk": "N"}}"#);
+        let column_names = names(&["id", "sk", "x"]);
+        let mut rows = rows(&[
+            &["b", "1", "p"],
+            &["a", "1", "q"],
+            &["b", "01.0", "r"],
+            &["B", "1", "s"],
+            &["a", "x", "t"],
+        ]);
+        rows.push(vec![Some("a".to_string()), None, Some("u".to_string())]);
+        rows.push(vec![Some("a".to_string()), None, Some("v".to_string())]);
+
+        let records = group_rows(
+            Some(&rule),
+            &column_names,
+            &parser,
+            &HashMap::new(),
+            rows.iter().zip(2..).collect(),
+        );
+        // sk is N, so 1 and 01.0 are the same group, invalid numbers are compared as text
+        assert_eq!(
+            group_lines(&records),
+            vec![vec![2, 4], vec![3], vec![5], vec![6], vec![7, 8]]
+        );
+        assert_eq!(records[0].line, 2);
+
+        // id is compared as text, unless it's a key of type N
+        let rule: GroupRule = from_str(r#"{"by": ["id"]}"#).unwrap();
+        let rows = self::rows(&[&["1"], &["1.0"]]);
+        let table_attrs: HashMap<_, _> = vec![("id".to_string(), "N".to_string())]
+            .into_iter()
+            .collect();
+        let records = group_rows(
+            Some(&rule),
+            &names(&["id"]),
+            &parser,
+            &HashMap::new(),
+            rows.iter().zip(2..).collect(),
+        );
+        assert_eq!(records.len(), 2);
+        let records = group_rows(
+            Some(&rule),
+            &names(&["id"]),
+            &parser,
+            &table_attrs,
+            rows.iter().zip(2..).collect(),
+        );
+        assert_eq!(records.len(), 1);
+    }
+
+    #[test]
+    fn other_columns_must_be_the_same_in_a_group() {
+        let rule: GroupRule = from_str(
+            r#"{"by": ["id"], "collect": {"lines": {"columns": ["item"]}},
+                "aggregate": {"total": {"function": "sum", "column": "price"}}}"#,
+        )
+        .unwrap();
+        let parser = parser(r#"{"columns": {"note": "skip"}}"#);
+        let column_names = names(&["id", "item", "price", "note", "status"]);
+        let rows = rows(&[
+            &["1", "a", "1.5", "x", "paid"],
+            &["1", "b", "2", "y", "paid"],
+            &["1", "c", "3", "z", "refunded"],
+        ]);
+        let rows: Vec<_> = rows.iter().collect();
+
+        assert_eq!(
+            check_group_values(&parser, &rule, &column_names, &rows[..2]),
+            Ok(())
+        );
+        assert_eq!(
+            check_group_values(&parser, &rule, &column_names, &rows),
+            Err(
+                "Column status has different values in rows of the group: paid | refunded"
+                    .to_string()
+            )
+        );
+
+        // computed columns are added after the columns of rows
+        let mut with_computed = column_names.to_owned();
+        with_computed.push("SK".to_string());
+        assert_eq!(
+            check_group_values(&parser, &rule, &with_computed, &rows[..2]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn aggregates_ignore_empty_null_and_missing_values() {
+        let rule: GroupRule = from_str(
+            r#"{"by": ["id"], "aggregate": {
+                "sum": {"function": "sum", "column": "price"},
+                "min": {"function": "min", "column": "price"},
+                "max": {"function": "max", "column": "price"},
+                "count": {"function": "count", "column": "price"},
+                "rows": {"function": "count"}
+            }}"#,
+        )
+        .unwrap();
+        let parser = parser("{}");
+        let column_names = names(&["id", "price"]);
+        let mut rows = rows(&[
+            &["1", "0.1"],
+            &["1", "10"],
+            &["1", ""],
+            &["1", "null"],
+            &["1", "-2.50"],
+            &["1", "0.2"],
+        ]);
+        rows.push(vec![Some("1".to_string()), None]);
+        let rows: Vec<_> = rows.iter().collect();
+
+        let attrs = build_group_attrs(&parser, &rule, &column_names, &rows).unwrap();
+        assert_eq!(attrs["sum"], number("7.8"));
+        assert_eq!(attrs["min"], number("-2.50"));
+        assert_eq!(attrs["max"], number("10"));
+        assert_eq!(attrs["count"], number("4"));
+        assert_eq!(attrs["rows"], number("7"));
+
+        // min and max are left out without values, an invalid number fails the group
+        let empty = self::rows(&[&["1", ""]]);
+        let attrs = build_group_attrs(&parser, &rule, &column_names, &[&empty[0]]).unwrap();
+        assert_eq!(attrs["sum"], number("0"));
+        assert!(!attrs.contains_key("min") && !attrs.contains_key("max"));
+
+        let invalid = self::rows(&[&["1", "abc"]]);
+        assert!(build_group_attrs(&parser, &rule, &column_names, &[&invalid[0]]).is_err());
+    }
+
+    #[test]
+    fn collect_rows_into_list_or_set() {
+        let rule: GroupRule = from_str(
+            r#"{"by": ["id"], "collect": {
+                "lines": {"columns": ["item", "qty"]},
+                "tags": {"columns": ["tag"], "type": "SS"},
+                "sizes": {"columns": ["qty"], "type": "NS"}
+            }}"#,
+        )
+        .unwrap();
+        let parser = parser(r#"{"columns": {"item": "S"}}"#);
+        let column_names = names(&["id", "item", "qty", "tag"]);
+        let rows = rows(&[
+            &["1", "a", "2", "x"],
+            &["1", "b", "2.0", "x"],
+            &["1", "", "3", ""],
+        ]);
+        let rows: Vec<_> = rows.iter().collect();
+
+        let attrs = build_group_attrs(&parser, &rule, &column_names, &rows).unwrap();
+        let lines = attrs["lines"].l.as_ref().unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1].m.as_ref().unwrap()["qty"], number("2.0"));
+        assert_eq!(attrs["tags"].ss, Some(vec!["x".to_string()]));
+        assert_eq!(
+            attrs["sizes"].ns,
+            Some(vec!["2".to_string(), "3".to_string()])
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use super::config::InferConfig;
+use super::error::{Error, Result};
+use super::parser::{attr_type, Parser};
+use super::schema::{load_schema_value, ColumnType, Schema};
+use serde_json::{to_value, Map, Value};
+use std::{collections::HashMap, fs};
+
+// number of rows of each inferred type in a column
+struct ColumnStats {
+    column_name: String,
+    type_counts: HashMap<ColumnType, usize>,
+
+    // rows which cannot be converted to the type declared in schema
+    invalid_count: usize,
+}
+
+impl ColumnStats {
+    // types sorted by count, the most frequent first
+    fn sorted_types(&self) -> Vec<(ColumnType, usize)> {
+        let mut types: Vec<_> = self.type_counts.iter().map(|(k, v)| (*k, *v)).collect();
+        types.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.to_string().cmp(&y.0.to_string())));
+        types
+    }
+
+    // null is ignored, since null values are usually omitted
+    fn dominant_type(&self) -> Option<ColumnType> {
+        self.sorted_types()
+            .into_iter()
+            .map(|x| x.0)
+            .find(|x| *x != ColumnType::Null)
+    }
+
+    fn is_consistent(&self) -> bool {
+        self.invalid_count == 0
+            && self
+                .type_counts
+                .keys()
+                .filter(|x| **x != ColumnType::Null)
+                .count()
+                <= 1
+    }
+}
+
+// infer the type of every row (or a sample), then report types of each column
+pub fn infer_types(
+    config: InferConfig,
+    header: &[String],
+    rows: &[Vec<String>],
+    lines: &[u64],
+) -> Result<()> {
+    let mut schema = config.schema;
+    let column_names = schema
+        .apply_header_annotations(header)
+        .and_then(|column_names| schema.apply_mapping(&column_names))
+        .map_err(|error| Error::Config(format!("Invalid header. {}", error)))?;
+
+    let parser = Parser {
+        allow_set: config.allow_set,
+        allow_null: true,
+        empty_policy: config.empty_policy,
+        detect_dynamodb_json: config.detect_dynamodb_json,
+        schema,
+    };
+
+    let mut all_stats: Vec<_> = column_names
+        .iter()
+        .map(|column_name| ColumnStats {
+            column_name: column_name.to_owned(),
+            type_counts: HashMap::new(),
+            invalid_count: 0,
+        })
+        .collect();
+
+    let sampled_rows = sample_rows(rows, lines, config.sample_size);
+    for (row, line) in &sampled_rows {
+        if row.len() != column_names.len() {
+            println!(
+                "Mismatch between header and row at line {}. Row ignored: {}",
+                line,
+                row.join(" | ")
+            );
+            continue;
+        }
+
+        for (i, stats) in all_stats.iter_mut().enumerate() {
+            if parser.is_skipped(&stats.column_name) {
+                continue;
+            }
+
+            // omitted attributes are not counted
+            match parser.build_cell_attr(&stats.column_name, None, row[i].to_string()) {
+                Ok(Some(attr)) => *stats.type_counts.entry(attr_type(&attr)).or_insert(0) += 1,
+                Ok(None) => {}
+                Err(_) => stats.invalid_count += 1,
+            }
+        }
+    }
+
+    print_report(&all_stats, sampled_rows.len());
+
+    if let Some(output_filename) = config.output_filename {
+        save_schema(
+            &all_stats,
+            &parser.schema,
+            config.schema_filename.as_deref(),
+            &output_filename,
+        )?;
+        println!("Schema has been saved to {}", output_filename);
+    }
+    println!();
+
+    Ok(())
+}
+
+// evenly spaced rows with their lines, so the sample covers the whole file
+fn sample_rows<'a>(
+    rows: &'a [Vec<String>],
+    lines: &'a [u64],
+    sample_size: Option<usize>,
+) -> Vec<(&'a Vec<String>, &'a u64)> {
+    match sample_size {
+        Some(size) if size < rows.len() => {
+            let step = rows.len() as f64 / size as f64;
+            (0..size)
+                .map(|i| (i as f64 * step) as usize)
+                .map(|i| (&rows[i], &lines[i]))
+                .collect()
+        }
+        _ => rows.iter().zip(lines).collect(),
+    }
+}
+
+// example:
+// Count: N 98 (98.00%), S 2 (2.00%) <- INCONSISTENT, dominant type N
+fn print_report(all_stats: &[ColumnStats], row_count: usize) {
+    println!("Inferred types of {} rows:", row_count);
+
+    let mut inconsistent_count = 0;
+    for stats in all_stats {
+        if stats.type_counts.is_empty() && stats.invalid_count == 0 {
+            println!("{}: skip", stats.column_name);
+            continue;
+        }
+
+        let mut counts: Vec<_> = stats
+            .sorted_types()
+            .into_iter()
+            .map(|(column_type, count)| (column_type.to_string(), count))
+            .collect();
+        if stats.invalid_count > 0 {
+            counts.push(("invalid".to_string(), stats.invalid_count));
+        }
+
+        let text = counts
+            .iter()
+            .map(|(name, count)| {
+                format!(
+                    "{} {} ({:.2}%)",
+                    name,
+                    count,
+                    100.0 * *count as f64 / row_count as f64
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if stats.is_consistent() {
+            println!("{}: {}", stats.column_name, text);
+        } else {
+            inconsistent_count += 1;
+            println!(
+                "{}: {} <- INCONSISTENT, dominant type {}",
+                stats.column_name,
+                text,
+                stats
+                    .dominant_type()
+                    .map(|x| x.to_string())
+                    .unwrap_or_else(|| "unknown".to_string())
+            );
+        }
+    }
+
+    println!(
+        "{} of {} columns have inconsistent types",
+        inconsistent_count,
+        all_stats.len()
+    );
+}
+
+// pin the dominant type of each column, in the format of schema file (json or toml)
+// types are added to the schema given by --schema, so its options, mapping and words are kept
+// a type decided by the schema (type, timestamp or dynamodb_json) is never replaced
+// columns of null or empty cells only are left out, so they are still inferred
+// e.g. a sample may only have empty cells of a column which has values in other rows
+// skipped columns are already declared in the schema, by type or drop
+fn save_schema(
+    all_stats: &[ColumnStats],
+    schema: &Schema,
+    schema_filename: Option<&str>,
+    filename: &str,
+) -> Result<()> {
+    let mut output = match schema_filename {
+        Some(schema_filename) => load_schema_value(schema_filename)?,
+        None => Value::Object(Map::new()),
+    };
+    let output_map = output.as_object_mut().unwrap();
+
+    // mapping and words from command line are added to the file
+    if !schema.rename.is_empty() {
+        output_map.insert("rename".to_string(), to_value(&schema.rename).unwrap());
+    }
+    if !schema.drop.is_empty() {
+        output_map.insert("drop".to_string(), to_value(&schema.drop).unwrap());
+    }
+    let tokens = to_value(&schema.tokens).unwrap();
+    if tokens.as_object().is_some_and(|x| !x.is_empty()) {
+        output_map.insert("tokens".to_string(), tokens);
+    }
+
+    let columns = output_map
+        .entry("columns")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .unwrap();
+    for stats in all_stats {
+        let column_type = match stats.dominant_type() {
+            Some(column_type) => column_type,
+            None => continue,
+        };
+
+        // a renamed column can be declared by either name
+        let declared_name = schema
+            .rename
+            .iter()
+            .find(|(old_name, new_name)| {
+                **new_name == stats.column_name && columns.contains_key(*old_name)
+            })
+            .map(|(old_name, _)| old_name.to_owned())
+            .unwrap_or_else(|| stats.column_name.to_owned());
+
+        match columns.get_mut(&declared_name) {
+            Some(Value::Object(declaration)) => {
+                let is_typed = ["type", "timestamp", "dynamodb_json"]
+                    .iter()
+                    .any(|x| declaration.contains_key(*x));
+                if !is_typed {
+                    declaration.insert("type".to_string(), column_type.to_string().into());
+                }
+            }
+            Some(_) => {}
+            None => {
+                columns.insert(declared_name, column_type.to_string().into());
+            }
+        }
+    }
+
+    let text = if filename.to_lowercase().ends_with(".toml") {
+        toml::Value::try_from(&output)
+            .and_then(|x| toml::to_string(&x))
+            .map_err(|error| Error::Config(format!("Cannot convert schema to toml. {}", error)))?
+    } else {
+        serde_json::to_string_pretty(&output).unwrap()
+    };
+    fs::write(filename, text)
+        .map_err(|error| Error::Io(format!("Cannot save schema file {}. {}", filename, error)))
+}
@@ -0,0 +1,106 @@
+use super::error::Error;
+use super::schema::{JoinType, LookupRule, MissingPolicy, Schema};
+use super::utility::parse_csv;
+use std::collections::HashMap;
+
+// a lookup file in memory, indexed by its key column
+pub struct Lookup {
+    rule: LookupRule,
+
+    // index of the join column in csv
+    column_index: usize,
+
+    // key -> values of selected columns
+    values: HashMap<String, Vec<String>>,
+
+    // number of selected columns, which are missing in rows without match
+    column_count: usize,
+}
+
+// load lookup files, columns brought in are added to the end of header
+pub fn load_lookups(
+    schema: &Schema,
+    column_names: &[String],
+) -> Result<(Vec<Lookup>, Vec<String>), Error> {
+    let invalid = |message: String| Error::Config(format!("Invalid lookup. {}", message));
+    let mut lookups = Vec::new();
+    let mut new_names = Vec::new();
+
+    for rule in &schema.lookups {
+        let column_index = column_names
+            .iter()
+            .position(|x| *x == rule.on)
+            .ok_or_else(|| invalid(format!("Column {} is not found in csv", rule.on)))?;
+
+        let (header, rows, lines) = parse_csv(rule.file.to_owned())?;
+        let key_name = rule.key.as_ref().unwrap_or(&rule.on);
+        let key_index = header
+            .iter()
+            .position(|x| x == key_name)
+            .ok_or_else(|| invalid(format!("Column {} is not found in {}", key_name, rule.file)))?;
+
+        // all columns except key by default
+        let selected: Vec<_> = match &rule.columns {
+            Some(columns) => columns
+                .iter()
+                .map(|x| {
+                    header.iter().position(|y| x == y).ok_or_else(|| {
+                        invalid(format!("Column {} is not found in {}", x, rule.file))
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            None => (0..header.len()).filter(|i| *i != key_index).collect(),
+        };
+
+        for i in &selected {
+            if column_names.contains(&header[*i]) || new_names.contains(&header[*i]) {
+                return Err(invalid(format!(
+                    "Column {} of {} is already a column",
+                    header[*i], rule.file
+                )));
+            }
+            new_names.push(header[*i].to_owned());
+        }
+
+        // the first row of a key is used
+        let mut values = HashMap::new();
+        for (row, line) in rows.iter().zip(lines) {
+            if row.len() != header.len() {
+                return Err(Error::Csv(format!(
+                    "Mismatch between header and row at line {} of {}",
+                    line, rule.file
+                )));
+            }
+            values
+                .entry(row[key_index].to_owned())
+                .or_insert_with(|| selected.iter().map(|i| row[*i].to_owned()).collect());
+        }
+
+        lookups.push(Lookup {
+            rule: rule.clone(),
+            column_index,
+            values,
+            column_count: selected.len(),
+        });
+    }
+
+    Ok((lookups, new_names))
+}
+
+impl Lookup {
+    // values of selected columns for the row, missing (None) if the row has no match
+    // None if the row is dropped by inner join, error if the row fails
+    pub fn find(&self, row: &[String]) -> Result<Option<Vec<Option<String>>>, String> {
+        let key = &row[self.column_index];
+
+        match (self.values.get(key), self.rule.join, self.rule.missing) {
+            (Some(values), _, _) => Ok(Some(values.iter().cloned().map(Some).collect())),
+            (None, JoinType::Inner, _) => Ok(None),
+            (None, JoinType::Left, MissingPolicy::Null) => Ok(Some(vec![None; self.column_count])),
+            (None, JoinType::Left, MissingPolicy::Fail) => Err(format!(
+                "{} {} is not found in {}",
+                self.rule.on, key, self.rule.file
+            )),
+        }
+    }
+}
@@ -1,4 +1,16 @@
 pub mod config;
 pub mod dynamo;
+pub mod error;
+pub mod export;
+pub mod group;
+pub mod infer;
+pub mod lookup;
+pub mod nested;
+pub mod number;
 pub mod parser;
+pub mod schema;
+pub mod script;
+pub mod template;
+pub mod timestamp;
 pub mod utility;
+pub mod validation;
@@ -0,0 +1,339 @@
+use super::parser::{build_list_attr, build_map_attr};
+use rusoto_dynamodb::AttributeValue;
+use std::collections::{BTreeMap, HashMap};
+
+// a step in the path of a nested attribute
+// e.g. address.lines[0] is Key(address), Key(lines), Index(0)
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+// attributes of an item, put together by their paths
+pub struct NestedItem {
+    root: HashMap<String, Node>,
+}
+
+enum Node {
+    Value(AttributeValue),
+    Map(HashMap<String, Node>),
+
+    // sorted by index, missing indexes are skipped
+    List(BTreeMap<usize, Node>),
+}
+
+// parse a header like address.city or tags[0] into a path
+// a header without dot or bracket is a path of a single key
+pub fn parse_path(column_name: &str) -> Result<Vec<PathSegment>, String> {
+    let mut path = Vec::new();
+    for part in column_name.split('.') {
+        let (name, mut indexes) = match part.find('[') {
+            Some(i) => part.split_at(i),
+            None => (part, ""),
+        };
+        if name.is_empty() {
+            return Err(format!("{} has an empty attribute name", column_name));
+        }
+        path.push(PathSegment::Key(name.to_owned()));
+
+        while let Some(rest) = indexes.strip_prefix('[') {
+            let (index, rest) = rest
+                .split_once(']')
+                .ok_or_else(|| format!("{} has an unclosed bracket", column_name))?;
+            let index = index
+                .parse()
+                .map_err(|_| format!("{} has an invalid list index [{}]", column_name, index))?;
+            path.push(PathSegment::Index(index));
+            indexes = rest;
+        }
+        if !indexes.is_empty() {
+            return Err(format!(
+                "{} has unexpected text after list index: {}",
+                column_name, indexes
+            ));
+        }
+    }
+
+    Ok(path)
+}
+
+// every path must lead to its own value
+// e.g. address conflicts with address.city, tags[0] conflicts with tags.name
+pub fn check_paths(column_names: &[&String], paths: &[&Vec<PathSegment>]) -> Result<(), String> {
+    for i in 0..paths.len() {
+        for j in 0..i {
+            if is_conflict(paths[j], paths[i]) {
+                return Err(format!(
+                    "Column {} conflicts with column {}",
+                    column_names[i], column_names[j]
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn is_conflict(a: &[PathSegment], b: &[PathSegment]) -> bool {
+    for (x, y) in a.iter().zip(b) {
+        match (x, y) {
+            (PathSegment::Key(x), PathSegment::Key(y)) if x != y => return false,
+            (PathSegment::Index(x), PathSegment::Index(y)) if x != y => return false,
+            (PathSegment::Key(_), PathSegment::Index(_))
+            | (PathSegment::Index(_), PathSegment::Key(_)) => return true,
+            _ => {}
+        }
+    }
+
+    // same path, or one path is inside the other
+    true
+}
+
+impl NestedItem {
+    pub fn new() -> NestedItem {
+        NestedItem {
+            root: HashMap::new(),
+        }
+    }
+
+    // paths must be checked by check_paths, so they never conflict
+    pub fn insert(&mut self, path: &[PathSegment], attr: AttributeValue) {
+        if let Some((PathSegment::Key(key), rest)) = path.split_first() {
+            insert_into_map(&mut self.root, key, rest, attr);
+        }
+    }
+
+    // maps and lists without any value are omitted, like empty cells
+    pub fn into_item(self) -> HashMap<String, AttributeValue> {
+        self.root
+            .into_iter()
+            .filter_map(|(key, node)| node.into_attr().map(|attr| (key, attr)))
+            .collect()
+    }
+}
+
+impl Node {
+    fn new(path: &[PathSegment]) -> Node {
+        match path.first() {
+            Some(PathSegment::Index(_)) => Node::List(BTreeMap::new()),
+            _ => Node::Map(HashMap::new()),
+        }
+    }
+
+    fn insert(&mut self, path: &[PathSegment], attr: AttributeValue) {
+        match (self, path.split_first()) {
+            (Node::Map(map), Some((PathSegment::Key(key), rest))) => {
+                insert_into_map(map, key, rest, attr)
+            }
+            (Node::List(list), Some((PathSegment::Index(index), rest))) => {
+                if rest.is_empty() {
+                    list.insert(*index, Node::Value(attr));
+                } else {
+                    list.entry(*index)
+                        .or_insert_with(|| Node::new(rest))
+                        .insert(rest, attr);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn into_attr(self) -> Option<AttributeValue> {
+        match self {
+            Node::Value(attr) => Some(attr),
+            Node::Map(map) => {
+                let map: HashMap<_, _> = map
+                    .into_iter()
+                    .filter_map(|(key, node)| node.into_attr().map(|attr| (key, attr)))
+                    .collect();
+                if map.is_empty() {
+                    None
+                } else {
+                    Some(build_map_attr(map))
+                }
+            }
+            Node::List(list) => {
+                let list: Vec<_> = list.into_values().filter_map(|x| x.into_attr()).collect();
+                if list.is_empty() {
+                    None
+                } else {
+                    Some(build_list_attr(list))
+                }
+            }
+        }
+    }
+}
+
+fn insert_into_map(
+    map: &mut HashMap<String, Node>,
+    key: &str,
+    rest: &[PathSegment],
+    attr: AttributeValue,
+) {
+    if rest.is_empty() {
+        map.insert(key.to_owned(), Node::Value(attr));
+    } else {
+        map.entry(key.to_owned())
+            .or_insert_with(|| Node::new(rest))
+            .insert(rest, attr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> PathSegment {
+        PathSegment::Key(name.to_string())
+    }
+
+    fn string(text: &str) -> AttributeValue {
+        AttributeValue {
+            s: Some(text.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn check(column_names: &[&str]) -> Result<(), String> {
+        let column_names: Vec<_> = column_names.iter().map(|x| x.to_string()).collect();
+        let paths: Vec<_> = column_names
+            .iter()
+            .map(|x| parse_path(x).unwrap())
+            .collect();
+        check_paths(
+            &column_names.iter().collect::<Vec<_>>(),
+            &paths.iter().collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn parse_valid_paths() {
+        assert_eq!(parse_path("id"), Ok(vec![key("id")]));
+        assert_eq!(parse_path("a.b"), Ok(vec![key("a"), key("b")]));
+        assert_eq!(
+            parse_path("tags[0]"),
+            Ok(vec![key("tags"), PathSegment::Index(0)])
+        );
+        assert_eq!(
+            parse_path("a[0].b"),
+            Ok(vec![key("a"), PathSegment::Index(0), key("b")])
+        );
+        assert_eq!(
+            parse_path("grid[1][12].x"),
+            Ok(vec![
+                key("grid"),
+                PathSegment::Index(1),
+                PathSegment::Index(12),
+                key("x")
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_invalid_paths() {
+        assert_eq!(
+            parse_path("a..b"),
+            Err("a..b has an empty attribute name".to_string())
+        );
+        assert_eq!(
+            parse_path(".a"),
+            Err(".a has an empty attribute name".to_string())
+        );
+        assert_eq!(
+            parse_path("[0]"),
+            Err("[0] has an empty attribute name".to_string())
+        );
+        assert_eq!(
+            parse_path("tags[0"),
+            Err("tags[0 has an unclosed bracket".to_string())
+        );
+        assert_eq!(
+            parse_path("tags[x]"),
+            Err("tags[x] has an invalid list index [x]".to_string())
+        );
+        assert_eq!(
+            parse_path("tags[-1]"),
+            Err("tags[-1] has an invalid list index [-1]".to_string())
+        );
+        assert_eq!(
+            parse_path("tags[0]x"),
+            Err("tags[0]x has unexpected text after list index: x".to_string())
+        );
+    }
+
+    #[test]
+    fn colliding_paths_are_reported() {
+        assert_eq!(check(&["id", "a.b", "a.c", "tags[0]", "tags[1]"]), Ok(()));
+        assert_eq!(check(&["a[0].b", "a[0].c", "a[1]"]), Ok(()));
+
+        assert_eq!(
+            check(&["address", "address.city"]),
+            Err("Column address.city conflicts with column address".to_string())
+        );
+        assert_eq!(
+            check(&["a.b.c", "a.b"]),
+            Err("Column a.b conflicts with column a.b.c".to_string())
+        );
+        assert_eq!(
+            check(&["tags[0]", "tags.name"]),
+            Err("Column tags.name conflicts with column tags[0]".to_string())
+        );
+        assert_eq!(
+            check(&["a[0]", "a[0].b"]),
+            Err("Column a[0].b conflicts with column a[0]".to_string())
+        );
+        assert!(check(&["a.b", "a.b"]).is_err());
+    }
+
+    #[test]
+    fn values_are_put_together_by_path() {
+        let mut item = NestedItem::new();
+        for (column_name, text) in [
+            ("id", "1"),
+            ("address.city", "Sydney"),
+            ("tags[1]", "b"),
+            ("tags[0]", "a"),
+            ("lines[3].sku", "x"),
+        ] {
+            item.insert(&parse_path(column_name).unwrap(), string(text));
+        }
+        let item = item.into_item();
+
+        assert_eq!(item["id"], string("1"));
+        assert_eq!(
+            item["address"],
+            build_map_attr(
+                vec![("city".to_string(), string("Sydney"))]
+                    .into_iter()
+                    .collect()
+            )
+        );
+        // lists are in order of index, missing indexes are skipped
+        assert_eq!(
+            item["tags"],
+            build_list_attr(vec![string("a"), string("b")])
+        );
+        assert_eq!(
+            item["lines"],
+            build_list_attr(vec![build_map_attr(
+                vec![("sku".to_string(), string("x"))].into_iter().collect()
+            )])
+        );
+    }
+
+    #[test]
+    fn maps_and_lists_without_values_are_omitted() {
+        let mut item = NestedItem::new();
+        item.insert(&parse_path("id").unwrap(), string("1"));
+        let item = item.into_item();
+        assert_eq!(item.len(), 1);
+
+        let node = Node::Map(
+            vec![("list".to_string(), Node::List(BTreeMap::new()))]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(node.into_attr(), None);
+    }
+}
@@ -0,0 +1,349 @@
+use std::{cmp::Ordering, fmt};
+
+// limits of DynamoDB number type
+// positive range: 1E-130 to 9.9999999999999999999999999999999999999E+125
+// negative range: -9.9999999999999999999999999999999999999E+125 to -1E-130
+const MAX_PRECISION: usize = 38;
+const MIN_EXPONENT: i64 = -130;
+const MAX_EXPONENT: i64 = 125;
+
+#[derive(Debug, PartialEq)]
+pub enum NumberError {
+    // not a number at all, e.g. "abc", "inf", "NaN"
+    Invalid,
+
+    // more than 38 significant digits
+    TooPrecise,
+
+    // exponent out of range, e.g. "1e400"
+    OutOfRange,
+}
+
+impl fmt::Display for NumberError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            NumberError::Invalid => "is not a valid number",
+            NumberError::TooPrecise => "has more than 38 significant digits",
+            NumberError::OutOfRange => "is out of the range of DynamoDB number",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+// a non-zero number in scientific notation, e.g. -0.0120 is (true, "12", -2)
+struct ParsedNumber {
+    is_negative: bool,
+    significant_digits: String,
+    exponent: i64,
+}
+
+// check that DynamoDB can store the number exactly
+// the text itself is never converted, so all digits are kept as is
+pub fn check_number(text: &str) -> Result<(), NumberError> {
+    if let Some(number) = parse_number(text)? {
+        if number.significant_digits.len() > MAX_PRECISION {
+            return Err(NumberError::TooPrecise);
+        }
+        if !(MIN_EXPONENT..=MAX_EXPONENT).contains(&number.exponent) {
+            return Err(NumberError::OutOfRange);
+        }
+    }
+    Ok(())
+}
+
+// canonical form of a number, so numbers of the same value have the same text
+// e.g. "1.50", "1.5" and "15e-1" are all "1.5E0"
+pub fn canonical_number(text: &str) -> Result<String, NumberError> {
+    check_number(text)?;
+
+    match parse_number(text)? {
+        None => Ok("0".to_string()),
+        Some(number) => {
+            let (first, rest) = number.significant_digits.split_at(1);
+            Ok(format!(
+                "{}{}{}{}E{}",
+                if number.is_negative { "-" } else { "" },
+                first,
+                if rest.is_empty() { "" } else { "." },
+                rest,
+                number.exponent
+            ))
+        }
+    }
+}
+
+// exact sum of numbers, e.g. "0.1" and "0.2" is "0.3"
+// the sum must fit in 38 significant digits, like any DynamoDB number
+pub fn sum_numbers(texts: &[&str]) -> Result<String, NumberError> {
+    let pow10 = |n: i64| 10i128.checked_pow(n as u32).ok_or(NumberError::TooPrecise);
+
+    // sum is sum * 10^-scale
+    let mut sum: i128 = 0;
+    let mut scale: i64 = 0;
+
+    for text in texts {
+        check_number(text)?;
+        let number = match parse_number(text)? {
+            Some(number) => number,
+            None => continue,
+        };
+
+        let mut mantissa: i128 = number.significant_digits.parse().unwrap();
+        if number.is_negative {
+            mantissa = -mantissa;
+        }
+        let mut number_scale = number.significant_digits.len() as i64 - 1 - number.exponent;
+        if number_scale < 0 {
+            mantissa = mantissa
+                .checked_mul(pow10(-number_scale)?)
+                .ok_or(NumberError::TooPrecise)?;
+            number_scale = 0;
+        }
+
+        if number_scale > scale {
+            sum = sum
+                .checked_mul(pow10(number_scale - scale)?)
+                .ok_or(NumberError::TooPrecise)?;
+            scale = number_scale;
+        } else {
+            mantissa = mantissa
+                .checked_mul(pow10(scale - number_scale)?)
+                .ok_or(NumberError::TooPrecise)?;
+        }
+        sum = sum.checked_add(mantissa).ok_or(NumberError::TooPrecise)?;
+    }
+
+    let digits = format!("{:0>width$}", sum.abs(), width = scale as usize + 1);
+    let (int_part, frac_part) = digits.split_at(digits.len() - scale as usize);
+    let frac_part = frac_part.trim_end_matches('0');
+    let text = format!(
+        "{}{}{}{}",
+        if sum < 0 { "-" } else { "" },
+        int_part,
+        if frac_part.is_empty() { "" } else { "." },
+        frac_part
+    );
+
+    check_number(&text)?;
+    Ok(text)
+}
+
+// compare the values of two numbers, e.g. "1.50" is equal to "1.5"
+pub fn compare_numbers(a: &str, b: &str) -> Result<Ordering, NumberError> {
+    let (a, b) = (parse_number(a)?, parse_number(b)?);
+    let sign = |x: &Option<ParsedNumber>| match x {
+        None => 0,
+        Some(number) if number.is_negative => -1,
+        Some(_) => 1,
+    };
+
+    match sign(&a).cmp(&sign(&b)) {
+        Ordering::Equal => {}
+        other => return Ok(other),
+    }
+
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            // digits are compared as text of the same length
+            let width = a.significant_digits.len().max(b.significant_digits.len());
+            let magnitude = a.exponent.cmp(&b.exponent).then_with(|| {
+                format!("{:0<width$}", a.significant_digits, width = width).cmp(&format!(
+                    "{:0<width$}",
+                    b.significant_digits,
+                    width = width
+                ))
+            });
+            Ok(if a.is_negative {
+                magnitude.reverse()
+            } else {
+                magnitude
+            })
+        }
+
+        // both are zero
+        _ => Ok(Ordering::Equal),
+    }
+}
+
+// returns None for zero
+fn parse_number(text: &str) -> Result<Option<ParsedNumber>, NumberError> {
+    let (is_negative, unsigned) = match text.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(i) => (&unsigned[..i], parse_exponent(&unsigned[i + 1..])?),
+        None => (unsigned, 0),
+    };
+
+    let (int_part, frac_part) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+        None => (mantissa, ""),
+    };
+    if (int_part.is_empty() && frac_part.is_empty())
+        || !int_part.chars().all(|c| c.is_ascii_digit())
+        || !frac_part.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(NumberError::Invalid);
+    }
+
+    let digits = format!("{}{}", int_part, frac_part);
+    match (digits.find(|c| c != '0'), digits.rfind(|c| c != '0')) {
+        (Some(first), Some(last)) => Ok(Some(ParsedNumber {
+            is_negative,
+            significant_digits: digits[first..=last].to_string(),
+            // exponent of the first significant digit, e.g. 2 for 123.4 and -2 for 0.012
+            // an exponent near the limit of i64 overflows, e.g. 10e9223372036854775807
+            exponent: exponent
+                .checked_add(int_part.len() as i64 - 1 - first as i64)
+                .ok_or(NumberError::OutOfRange)?,
+        })),
+        _ => Ok(None),
+    }
+}
+
+fn parse_exponent(text: &str) -> Result<i64, NumberError> {
+    let digits = text
+        .strip_prefix('-')
+        .or_else(|| text.strip_prefix('+'))
+        .unwrap_or(text);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(NumberError::Invalid);
+    }
+
+    // exponent is too large even for i64
+    text.parse().map_err(|_| NumberError::OutOfRange)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_number_rejects_invalid_text() {
+        for text in [
+            "abc", "inf", "-inf", "NaN", "", "-", ".", "1e", "1e+", "1.2.3", "0x10",
+        ] {
+            assert_eq!(check_number(text), Err(NumberError::Invalid), "{}", text);
+        }
+        for text in ["0", "-0.0", "+1", ".5", "5.", "1.50", "-12e-3", "1E5"] {
+            assert_eq!(check_number(text), Ok(()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn check_number_limits_precision() {
+        let digits_38 = "1".repeat(38);
+        let digits_39 = "1".repeat(39);
+        assert_eq!(check_number(&digits_38), Ok(()));
+        assert_eq!(check_number(&digits_39), Err(NumberError::TooPrecise));
+        assert_eq!(
+            check_number(&format!("0.{}", digits_39)),
+            Err(NumberError::TooPrecise)
+        );
+
+        // leading and trailing zeros are not significant
+        assert_eq!(check_number(&format!("1{}", "0".repeat(40))), Ok(()));
+        assert_eq!(check_number(&format!("0.{}1", "0".repeat(40))), Ok(()));
+    }
+
+    #[test]
+    fn check_number_limits_range() {
+        assert_eq!(check_number("1E-130"), Ok(()));
+        assert_eq!(check_number("-1E-130"), Ok(()));
+        assert_eq!(check_number("1E-131"), Err(NumberError::OutOfRange));
+        assert_eq!(check_number("0.1E-129"), Ok(()));
+        assert_eq!(check_number("0.1E-130"), Err(NumberError::OutOfRange));
+
+        let max = format!("9.{}E+125", "9".repeat(37));
+        assert_eq!(check_number(&max), Ok(()));
+        assert_eq!(check_number(&format!("-{}", max)), Ok(()));
+        assert_eq!(check_number("1E+126"), Err(NumberError::OutOfRange));
+        assert_eq!(check_number("10E125"), Err(NumberError::OutOfRange));
+        assert_eq!(check_number("1e400"), Err(NumberError::OutOfRange));
+
+        // zero is in range whatever the exponent
+        assert_eq!(check_number("0e400"), Ok(()));
+    }
+
+    #[test]
+    fn check_number_handles_overflowing_exponent() {
+        for text in [
+            "10e9223372036854775807",
+            "0.01e-9223372036854775808",
+            "1e9223372036854775808",
+            "1e-99999999999999999999",
+        ] {
+            assert_eq!(check_number(text), Err(NumberError::OutOfRange), "{}", text);
+            assert_eq!(
+                canonical_number(text),
+                Err(NumberError::OutOfRange),
+                "{}",
+                text
+            );
+        }
+        assert!(compare_numbers("10e9223372036854775807", "1").is_err());
+    }
+
+    #[test]
+    fn canonical_number_is_same_for_same_value() {
+        assert_eq!(canonical_number("1.50"), Ok("1.5E0".to_string()));
+        assert_eq!(canonical_number("15e-1"), Ok("1.5E0".to_string()));
+        assert_eq!(canonical_number("+1.5"), Ok("1.5E0".to_string()));
+        assert_eq!(canonical_number("-0.0120"), Ok("-1.2E-2".to_string()));
+        assert_eq!(canonical_number("100"), Ok("1E2".to_string()));
+        assert_eq!(canonical_number("-0.00"), Ok("0".to_string()));
+        assert_eq!(canonical_number("abc"), Err(NumberError::Invalid));
+    }
+
+    #[test]
+    fn sum_numbers_is_exact() {
+        assert_eq!(sum_numbers(&["0.1", "0.2"]), Ok("0.3".to_string()));
+        assert_eq!(sum_numbers(&["1.50", "-1.5"]), Ok("0".to_string()));
+        assert_eq!(
+            sum_numbers(&["1e3", "2.5", "-0.25"]),
+            Ok("1002.25".to_string())
+        );
+        assert_eq!(sum_numbers(&[]), Ok("0".to_string()));
+        assert_eq!(sum_numbers(&["abc"]), Err(NumberError::Invalid));
+    }
+
+    #[test]
+    fn sum_numbers_at_scale() {
+        // 38 significant digits is the limit of the sum
+        assert_eq!(
+            sum_numbers(&["1e20", "1e-17"]),
+            Ok(format!("1{}.{}1", "0".repeat(20), "0".repeat(16)))
+        );
+        assert_eq!(
+            sum_numbers(&["1e20", "1e-18"]),
+            Err(NumberError::TooPrecise)
+        );
+
+        // far apart exponents cannot be summed exactly
+        assert_eq!(
+            sum_numbers(&["1e125", "1e-130"]),
+            Err(NumberError::TooPrecise)
+        );
+
+        let max = format!("9.{}E+125", "9".repeat(37));
+        assert!(sum_numbers(&[&max, &max]).is_err());
+        assert_eq!(
+            sum_numbers(&[&"9".repeat(38), "1"]),
+            Ok(format!("1{}", "0".repeat(38)))
+        );
+    }
+
+    #[test]
+    fn compare_numbers_by_value() {
+        assert_eq!(compare_numbers("1.50", "1.5"), Ok(Ordering::Equal));
+        assert_eq!(compare_numbers("0", "-0.0"), Ok(Ordering::Equal));
+        assert_eq!(compare_numbers("2", "10"), Ok(Ordering::Less));
+        assert_eq!(compare_numbers("-2", "-10"), Ok(Ordering::Greater));
+        assert_eq!(compare_numbers("-1", "0"), Ok(Ordering::Less));
+        assert_eq!(compare_numbers("0.0012", "1.2e-3"), Ok(Ordering::Equal));
+        assert_eq!(compare_numbers("1.21", "1.2"), Ok(Ordering::Greater));
+        assert_eq!(compare_numbers("x", "1"), Err(NumberError::Invalid));
+    }
+}
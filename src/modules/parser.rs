@@ -1,161 +1,877 @@
+use super::number::{canonical_number, check_number, NumberError};
+use super::schema::{
+    ArrayMode, BinaryEncoding, ColumnType, ElementType, EmptyPolicy, Schema, SplitRule, Tokens,
+};
+use super::timestamp::TimestampFormat;
 use bytes::Bytes;
 use itertools::Itertools;
 use rusoto_dynamodb::AttributeValue;
-use serde_json::{from_str, to_string, Value};
+use serde_json::{from_str, to_string, Number, Value};
 use std::collections::HashMap;
 
 pub struct Parser {
     pub allow_set: bool,
     pub allow_null: bool,
+
+    // policy of empty cells, unless declared in schema
+    // if there's no policy, empty cells are parsed like other values
+    pub empty_policy: Option<EmptyPolicy>,
+
+    // cells of valid DynamoDB JSON in inferred columns are saved as they are
+    pub detect_dynamodb_json: bool,
+    pub schema: Schema,
 }
 
 enum ArrayType {
     List,
     NumberSet,
     StringSet,
+    BinarySet,
+}
+
+// options of a column, which also apply to values nested in the column
+#[derive(Clone, Copy)]
+struct ColumnOptions<'a> {
+    array_mode: ArrayMode,
+
+    // binary encoding declared in schema
+    // if not declared, binary is utf8 text and binary set elements are base64
+    encoding: Option<BinaryEncoding>,
+
+    // words of true, false and null
+    tokens: &'a Tokens,
 }
 
 impl Parser {
-    pub fn build_attr(&self, column_type: Option<&String>, text: String) -> AttributeValue {
-        match column_type {
+    // build attribute for a csv cell, taking into account empty policy and allow_null
+    // returns None if the attribute should be left out of the item
+    // key attributes are checked against the key type, see check_key_attr
+    // null of a key is always left out, e.g. the item is not in a sparse index
+    pub fn build_cell_attr(
+        &self,
+        column_name: &str,
+        key_type: Option<&String>,
+        text: String,
+    ) -> Result<Option<AttributeValue>, String> {
+        if text.is_empty() {
+            let empty_policy = self
+                .schema
+                .column(column_name)
+                .and_then(|x| x.empty)
+                .or(self.empty_policy);
+
+            // a key is never saved empty or null, the batch would fail
+            match empty_policy {
+                Some(EmptyPolicy::Omit) => return Ok(None),
+                Some(EmptyPolicy::Null) if key_type.is_some() => return Ok(None),
+                Some(EmptyPolicy::Null) => return Ok(Some(build_null_attr())),
+                Some(EmptyPolicy::String) => {
+                    let attribute = build_string_attr(text);
+                    return match key_type {
+                        Some(key_type) => check_key_attr(key_type, attribute).map(Some),
+                        None => Ok(Some(attribute)),
+                    };
+                }
+                Some(EmptyPolicy::Reject) => return Err("empty value is not allowed".to_string()),
+                None => {}
+            }
+
+            if let Some(key_type) = key_type {
+                return Err(format!(
+                    "key attribute of type {} cannot be empty",
+                    key_type
+                ));
+            }
+        }
+
+        let attribute = self.build_attr(column_name, key_type, text)?;
+        let attribute = match key_type {
+            Some(_) if attribute.null.is_some() => return Ok(None),
+            Some(key_type) => check_key_attr(key_type, attribute)?,
+            None => attribute,
+        };
+        if self.allow_null || attribute.null.is_none() {
+            Ok(Some(attribute))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // build attribute for a cell of a row, see build_cell_attr
+    // a missing cell is null whatever the type of the column, e.g. columns without match in lookup files
+    pub fn build_row_cell_attr(
+        &self,
+        column_name: &str,
+        key_type: Option<&String>,
+        cell: Option<String>,
+    ) -> Result<Option<AttributeValue>, String> {
+        match cell {
+            Some(text) => self.build_cell_attr(column_name, key_type, text),
+            None if key_type.is_none() && self.allow_null => Ok(Some(build_null_attr())),
+            None => Ok(None),
+        }
+    }
+
+    // build attribute for a column
+    // type declared in schema comes first, then type of key attribute, then inference
+    pub fn build_attr(
+        &self,
+        column_name: &str,
+        key_type: Option<&String>,
+        text: String,
+    ) -> Result<AttributeValue, String> {
+        let tokens = self.schema.column_tokens(column_name);
+        let options = self.column_options(column_name, &tokens);
+
+        if let Some(timestamp) = self
+            .schema
+            .column(column_name)
+            .and_then(|x| x.timestamp.as_ref())
+        {
+            return build_timestamp_attr(timestamp, &tokens, text);
+        }
+
+        if self
+            .schema
+            .column(column_name)
+            .is_some_and(|x| x.dynamodb_json)
+        {
+            return build_dynamodb_json_attr(key_type, &tokens, text);
+        }
+
+        if let Some(split) = self
+            .schema
+            .column(column_name)
+            .and_then(|x| x.split.as_ref())
+        {
+            let column_type = self.schema.column_type(column_name);
+            return self.build_split_attr(split, column_type, options, text);
+        }
+
+        if let Some(column_type) = self.schema.column_type(column_name) {
+            return self.build_typed_attr(column_type, options, text);
+        }
+
+        match key_type {
             // type is known, key attribute
             Some(some_type) => {
                 match some_type.as_str() {
                     // type is number
-                    "N" => build_number_attr(text),
+                    "N" => match check_number(&text) {
+                        Ok(_) => Ok(build_number_attr(text)),
+                        Err(error) => Err(format!("{} {}", text, error)),
+                    },
 
                     // type is byte
-                    "B" => build_bytes_attr(Bytes::from(text)),
+                    "B" => Ok(build_bytes_attr(decode_binary(
+                        &text,
+                        options.encoding.unwrap_or(BinaryEncoding::Utf8),
+                    )?)),
 
                     // type is string
-                    "S" => build_string_attr(text),
+                    "S" => Ok(build_string_attr(text)),
 
                     // in theory, we won't get other type for key
-                    _ => self.parse_string_as_attr(text),
+                    _ => self.parse_string_as_attr(text, options),
                 }
             }
             None => {
                 // type is unknown, non-key attribute
-                self.parse_string_as_attr(text)
+                self.parse_string_as_attr(text, options)
+            }
+        }
+    }
+
+    // build a set from values of a column in several rows, duplicates are removed
+    // empty and null values are ignored, None if no value is left
+    pub fn build_set_attr(
+        &self,
+        column_name: &str,
+        set_type: ColumnType,
+        values: Vec<String>,
+    ) -> Result<Option<AttributeValue>, String> {
+        let tokens = self.schema.column_tokens(column_name);
+        let values: Vec<_> = values
+            .into_iter()
+            .filter(|x| !x.is_empty() && !tokens.is_null(x))
+            .collect();
+        if values.is_empty() {
+            return Ok(None);
+        }
+
+        match set_type {
+            ColumnType::SS => Ok(Some(build_string_set_attr(
+                values.into_iter().unique().collect(),
+            ))),
+            ColumnType::NS => {
+                let mut numbers = Vec::new();
+                for x in values {
+                    match canonical_number(&x) {
+                        Ok(canonical) => numbers.push((canonical, x)),
+                        Err(error) => return Err(format!("{} {}", x, error)),
+                    }
+                }
+                // numbers of the same value are duplicates, e.g. 1.5 and 1.50
+                Ok(Some(build_number_set_attr(
+                    numbers
+                        .into_iter()
+                        .unique_by(|x| x.0.to_owned())
+                        .map(|x| x.1)
+                        .collect(),
+                )))
+            }
+            ColumnType::BS => {
+                let encoding = self
+                    .column_options(column_name, &tokens)
+                    .encoding
+                    .unwrap_or(BinaryEncoding::Base64);
+                let binaries: Vec<_> = values
+                    .iter()
+                    .map(|x| decode_binary(x, encoding))
+                    .collect::<Result<_, _>>()?;
+                Ok(Some(build_binary_set_attr(
+                    binaries.into_iter().unique().collect(),
+                )))
+            }
+            _ => Err(format!("{} is not a set type", set_type)),
+        }
+    }
+
+    // a column is dropped if its type is skip in schema
+    pub fn is_skipped(&self, column_name: &str) -> bool {
+        self.schema.column_type(column_name) == Some(ColumnType::Skip)
+    }
+
+    // array mode declared in schema comes first, then allow_set
+    fn column_options<'a>(&self, column_name: &str, tokens: &'a Tokens) -> ColumnOptions<'a> {
+        let column_schema = self.schema.column(column_name);
+        ColumnOptions {
+            array_mode: match column_schema.and_then(|x| x.array) {
+                Some(array_mode) => array_mode,
+                None if self.allow_set => ArrayMode::Auto,
+                None => ArrayMode::List,
+            },
+            encoding: column_schema.and_then(|x| x.encoding),
+            tokens,
+        }
+    }
+
+    // build attribute of the type declared in schema
+    // returns error if the text cannot be converted to the type
+    fn build_typed_attr(
+        &self,
+        column_type: ColumnType,
+        options: ColumnOptions,
+        text: String,
+    ) -> Result<AttributeValue, String> {
+        // same as inference, null is still null unless the column is string or binary
+        if options.tokens.is_null(&text)
+            && column_type != ColumnType::S
+            && column_type != ColumnType::B
+        {
+            return Ok(build_null_attr());
+        }
+
+        // the column itself is always the declared type, array mode only applies to nested arrays
+        let set_options = |array_mode| ColumnOptions {
+            array_mode,
+            ..options
+        };
+
+        match column_type {
+            ColumnType::S => Ok(build_string_attr(text)),
+
+            ColumnType::N => match check_number(&text) {
+                Ok(_) => Ok(build_number_attr(text)),
+                Err(error) => Err(format!("{} {}", text, error)),
+            },
+
+            ColumnType::B => Ok(build_bytes_attr(decode_binary(
+                &text,
+                options.encoding.unwrap_or(BinaryEncoding::Utf8),
+            )?)),
+
+            ColumnType::Bool => match options.tokens.parse_bool(&text) {
+                Some(b) => Ok(build_bool_attr(b)),
+                None => Err(format!("{} is not a valid bool", text)),
+            },
+
+            ColumnType::Null => Ok(build_null_attr()),
+
+            ColumnType::SS => {
+                self.build_array_attr(parse_json_array(&text)?, set_options(ArrayMode::StringSet))
+            }
+
+            ColumnType::NS => {
+                self.build_array_attr(parse_json_array(&text)?, set_options(ArrayMode::NumberSet))
+            }
+
+            ColumnType::BS => {
+                self.build_array_attr(parse_json_array(&text)?, set_options(ArrayMode::BinarySet))
             }
+
+            ColumnType::L => self.build_list_attr(parse_json_array(&text)?, options),
+
+            ColumnType::M => match from_str::<Value>(&text) {
+                Ok(Value::Object(map)) => self.parse_json_as_attr(Value::Object(map), options),
+                _ => Err(format!("{} is not a valid json object", text)),
+            },
+
+            ColumnType::Json => match from_str::<Value>(&text) {
+                Ok(json) => self.parse_json_as_attr(json, options),
+                Err(_) => Err(format!("{} is not valid json", text)),
+            },
+
+            // skipped columns are removed before building attributes
+            ColumnType::Skip => Err("skipped column cannot be converted".to_string()),
         }
     }
 
+    // split a delimited cell into an array, then convert it like a json array
+    // declared type of the column decides list or set, otherwise array mode does
+    fn build_split_attr(
+        &self,
+        split: &SplitRule,
+        column_type: Option<ColumnType>,
+        options: ColumnOptions,
+        text: String,
+    ) -> Result<AttributeValue, String> {
+        if options.tokens.is_null(&text) {
+            return Ok(build_null_attr());
+        }
+
+        let array_mode = match column_type {
+            Some(ColumnType::L) => ArrayMode::List,
+            Some(ColumnType::SS) => ArrayMode::StringSet,
+            Some(ColumnType::NS) => ArrayMode::NumberSet,
+            Some(ColumnType::BS) => ArrayMode::BinarySet,
+            _ => options.array_mode,
+        };
+        self.build_array_attr(
+            split_cell(split, &text)?,
+            ColumnOptions {
+                array_mode,
+                ..options
+            },
+        )
+    }
+
     // try to parse the string as different types of attribute
-    // order: null, bool, number, json (complex value), string
-    fn parse_string_as_attr(&self, text: String) -> AttributeValue {
-        let parsed_as_null = text == "null";
-        let parsed_as_number = text.parse::<f64>();
-        let parsed_as_bool = text.parse::<bool>();
+    // order: null, bool, number, DynamoDB JSON (if detected), json (complex value), string
+    // bool comes before number, so words like 1 and 0 can be declared as bool
+    fn parse_string_as_attr(
+        &self,
+        text: String,
+        options: ColumnOptions,
+    ) -> Result<AttributeValue, String> {
+        let parsed_as_null = options.tokens.is_null(&text);
+        let parsed_as_bool = options.tokens.parse_bool(&text);
+        let parsed_as_number = check_number(&text);
         let parsed_as_json_value = from_str::<Value>(&text);
 
         if parsed_as_null {
-            build_null_attr()
-        } else if parsed_as_number.is_ok() {
-            build_number_attr(text)
-        } else if parsed_as_bool.is_ok() {
-            build_bool_attr(parsed_as_bool.unwrap())
-        } else if parsed_as_json_value.is_ok() {
-            self.parse_json_as_attr(parsed_as_json_value.unwrap())
+            Ok(build_null_attr())
+        } else if let Some(b) = parsed_as_bool {
+            Ok(build_bool_attr(b))
+        } else if parsed_as_number != Err(NumberError::Invalid) {
+            // numbers which DynamoDB cannot store are kept as string
+            Ok(build_inferred_number_attr(text))
+        } else if let Ok(json) = parsed_as_json_value {
+            match parse_dynamodb_json(&json) {
+                Ok(attribute) if self.detect_dynamodb_json => Ok(attribute),
+                _ => self.parse_json_as_attr(json, options),
+            }
         } else {
-            build_string_attr(text)
+            Ok(build_string_attr(text))
         }
     }
 
-    fn parse_json_as_attr(&self, json: Value) -> AttributeValue {
+    // returns error only if an array cannot be converted to the set required by array mode
+    fn parse_json_as_attr(
+        &self,
+        json: Value,
+        options: ColumnOptions,
+    ) -> Result<AttributeValue, String> {
         match json {
-            Value::Null => build_null_attr(),
-
-            Value::Bool(x) => build_bool_attr(x),
-
-            Value::Number(x) => build_number_attr(to_string(&x).unwrap()),
-
-            Value::String(x) => build_string_attr(x),
-
-            Value::Array(array) => {
-                let array_type = self.parse_json_array_type(&array);
-
-                match array_type {
-                    ArrayType::List => build_list_attr(
-                        array
-                            .into_iter()
-                            .filter(|x| !x.is_null() || self.allow_null) // ignore null if not allowed
-                            .map(|x| self.parse_json_as_attr(x))
-                            .collect(),
-                    ),
-                    ArrayType::StringSet => build_string_set_attr(
-                        // set won't have null
-                        array
-                            .into_iter()
-                            .map(|x| x.as_str().unwrap().to_string())
-                            .collect(),
-                    ),
-                    ArrayType::NumberSet => build_number_set_attr(
-                        // set won't have null
-                        array
-                            .into_iter()
-                            .map(|x| x.as_str().unwrap().to_string())
-                            .collect(),
-                    ),
-                }
-            }
+            Value::Null => Ok(build_null_attr()),
+
+            Value::Bool(x) => Ok(build_bool_attr(x)),
+
+            // original digits are kept (serde_json arbitrary_precision)
+            Value::Number(x) => Ok(build_inferred_number_attr(to_string(&x).unwrap())),
+
+            // declared words of true, false and null also apply to strings in json
+            Value::String(x) => match options.tokens.parse_json_string(&x) {
+                Some(json) => self.parse_json_as_attr(json, options),
+                None => Ok(build_string_attr(x)),
+            },
+
+            Value::Array(array) => self.build_array_attr(array, options),
 
             Value::Object(dictionary) => {
                 let mut map_attribute = HashMap::new();
                 for (k, v) in dictionary {
-                    let v_attribute = self.parse_json_as_attr(v);
+                    let v_attribute = self.parse_json_as_attr(v, options)?;
                     if v_attribute.null.is_none() || self.allow_null {
                         // ignore null if not allowed
                         map_attribute.insert(k, v_attribute);
                     }
                 }
-                build_map_attr(map_attribute)
+                Ok(build_map_attr(map_attribute))
             }
         }
     }
 
-    // a list in the json can be either List or Set in dynamodb
-    // this method takes into account allow_set
-    fn parse_json_array_type(&self, list: &Vec<Value>) -> ArrayType {
-        if !self.allow_set {
-            ArrayType::List
-        } else {
-            // set cannot be empty in dynamodb
-            if list.is_empty() {
-                ArrayType::List
+    fn build_list_attr(
+        &self,
+        array: Vec<Value>,
+        options: ColumnOptions,
+    ) -> Result<AttributeValue, String> {
+        let mut list = Vec::new();
+        for x in array {
+            let attribute = self.parse_json_as_attr(x, options)?;
+            if attribute.null.is_none() || self.allow_null {
+                // ignore null if not allowed
+                list.push(attribute);
+            }
+        }
+        Ok(build_list_attr(list))
+    }
+
+    // a json array can be list or any type of set, depending on array mode
+    fn build_array_attr(
+        &self,
+        array: Vec<Value>,
+        options: ColumnOptions,
+    ) -> Result<AttributeValue, String> {
+        let encoding = options.encoding.unwrap_or(BinaryEncoding::Base64);
+
+        match parse_json_array_type(&array, options.array_mode, encoding)? {
+            ArrayType::List => self.build_list_attr(array, options),
+            // set won't have null
+            ArrayType::StringSet => Ok(build_string_set_attr(
+                array
+                    .into_iter()
+                    .map(|x| x.as_str().unwrap().to_string())
+                    .collect(),
+            )),
+            // number text is kept as is
+            ArrayType::NumberSet => Ok(build_number_set_attr(
+                array.iter().map(|x| to_string(x).unwrap()).collect(),
+            )),
+            ArrayType::BinarySet => Ok(build_binary_set_attr(
+                array
+                    .iter()
+                    .map(|x| decode_binary(x.as_str().unwrap(), encoding))
+                    .collect::<Result<_, _>>()?,
+            )),
+        }
+    }
+}
+
+// a list in the json can be either List or Set in dynamodb
+// a set is required if array mode is a set type, otherwise it is optional (auto) or not allowed (list)
+fn parse_json_array_type(
+    list: &[Value],
+    array_mode: ArrayMode,
+    encoding: BinaryEncoding,
+) -> Result<ArrayType, String> {
+    // set cannot be empty in dynamodb
+    let can_be_set = !list.is_empty();
+
+    match array_mode {
+        ArrayMode::List => Ok(ArrayType::List),
+        ArrayMode::Auto => {
+            if can_be_set && is_string_set(list) {
+                Ok(ArrayType::StringSet)
+            } else if can_be_set && is_number_set(list) {
+                Ok(ArrayType::NumberSet)
             } else {
-                if is_string_set(&list) {
-                    ArrayType::StringSet
-                } else if is_number_set(&list) {
-                    ArrayType::NumberSet
-                } else {
-                    ArrayType::List
-                }
+                Ok(ArrayType::List)
             }
         }
+        ArrayMode::StringSet if can_be_set && is_string_set(list) => Ok(ArrayType::StringSet),
+        ArrayMode::NumberSet if can_be_set && is_number_set(list) => Ok(ArrayType::NumberSet),
+        ArrayMode::BinarySet if can_be_set && is_binary_set(list, encoding) => {
+            Ok(ArrayType::BinarySet)
+        }
+        ArrayMode::StringSet => Err(format!(
+            "{} is not a non-empty set of unique strings",
+            to_string(list).unwrap()
+        )),
+        ArrayMode::NumberSet => Err(format!(
+            "{} is not a non-empty set of unique numbers",
+            to_string(list).unwrap()
+        )),
+        ArrayMode::BinarySet => Err(format!(
+            "{} is not a non-empty set of unique {} binaries",
+            to_string(list).unwrap(),
+            encoding
+        )),
+    }
+}
+
+// elements of a delimited cell as json values
+// numbers are checked like cells of N, and kept as json numbers of the same digits, e.g. 007
+// inferred elements are numbers if a cell of the same text would be
+fn split_cell(split: &SplitRule, text: &str) -> Result<Vec<Value>, String> {
+    let build_number = |x: &str| Value::Number(Number::from_string_unchecked(x.to_string()));
+
+    text.split(split.delimiter.as_str())
+        .map(|x| if split.trim { x.trim() } else { x })
+        .filter(|x| !x.is_empty())
+        .map(|x| match (split.element, check_number(x)) {
+            (Some(ElementType::S), _) => Ok(Value::String(x.to_string())),
+            (Some(ElementType::N), Ok(_)) => Ok(build_number(x)),
+            (Some(ElementType::N), Err(error)) => Err(format!("{} {}", x, error)),
+            (None, Err(NumberError::Invalid)) => Ok(Value::String(x.to_string())),
+            (None, _) => Ok(build_number(x)),
+        })
+        .collect()
+}
+
+// a key attribute must be of the key type, and cannot be empty
+// otherwise DynamoDB rejects the whole batch, rather than the row
+// this includes empty cells saved by the empty policy
+fn check_key_attr(key_type: &str, attribute: AttributeValue) -> Result<AttributeValue, String> {
+    let is_empty = attribute.s.as_ref().is_some_and(|x| x.is_empty())
+        || attribute.b.as_ref().is_some_and(|x| x.is_empty());
+    if is_empty {
+        return Err(format!(
+            "key attribute of type {} cannot be empty",
+            key_type
+        ));
+    }
+
+    let text = attr_to_text(&attribute);
+    let attribute_type = attr_type(&attribute).to_string();
+    if attribute_type != key_type {
+        return Err(format!(
+            "{} is {} rather than key type {}",
+            text, attribute_type, key_type
+        ));
+    }
+
+    // digits are saved as they are, DynamoDB compares numbers by value
+    if let Some(number) = &attribute.n {
+        check_number(number).map_err(|error| format!("{} {}", number, error))?;
+    }
+
+    Ok(attribute)
+}
+
+// a cell of DynamoDB JSON, which must be of the key type if the column is a key
+fn build_dynamodb_json_attr(
+    key_type: Option<&String>,
+    tokens: &Tokens,
+    text: String,
+) -> Result<AttributeValue, String> {
+    if tokens.is_null(&text) {
+        return Ok(build_null_attr());
+    }
+
+    let attribute = match from_str::<Value>(&text) {
+        Ok(json) => parse_dynamodb_json(&json),
+        Err(_) => Err("invalid json".to_string()),
+    }
+    .map_err(|reason| format!("{} is not valid DynamoDB JSON. {}", text, reason))?;
+
+    match key_type {
+        Some(key_type) if attr_type(&attribute).to_string() != *key_type => {
+            Err(format!("{} is not of key type {}", text, key_type))
+        }
+        _ => Ok(attribute),
+    }
+}
+
+// a value with exactly one type descriptor, e.g. {"N": "1.50"} or {"M": {"a": {"S": "x"}}}
+// values are checked as DynamoDB would, numbers are kept as they are
+fn parse_dynamodb_json(json: &Value) -> Result<AttributeValue, String> {
+    let (descriptor, value) = match json {
+        Value::Object(map) if map.len() == 1 => map.iter().next().unwrap(),
+        _ => return Err("expected an object of one type descriptor".to_string()),
+    };
+    let invalid = || format!("invalid value of {}", descriptor);
+    let as_str = |x: &Value| x.as_str().map(|x| x.to_string()).ok_or_else(invalid);
+    let as_array = |x: &Value| match x.as_array() {
+        Some(array) if !array.is_empty() => array.iter().map(as_str).collect(),
+        Some(_) => Err(format!("{} cannot be empty", descriptor)),
+        None => Err(invalid()),
+    };
+    let as_number = |x: String| match check_number(&x) {
+        Ok(_) => Ok(x),
+        Err(error) => Err(format!("{} {}", x, error)),
+    };
+    let as_binary = |x: String| decode_binary(&x, BinaryEncoding::Base64);
+    let check_unique = |count: usize, unique_count: usize| {
+        if count == unique_count {
+            Ok(())
+        } else {
+            Err(format!("{} has duplicates", descriptor))
+        }
+    };
+
+    match descriptor.as_str() {
+        "S" => Ok(build_string_attr(as_str(value)?)),
+        "N" => Ok(build_number_attr(as_number(as_str(value)?)?)),
+        "B" => Ok(build_bytes_attr(as_binary(as_str(value)?)?)),
+        "BOOL" => Ok(build_bool_attr(value.as_bool().ok_or_else(invalid)?)),
+        "NULL" if value == &Value::Bool(true) => Ok(build_null_attr()),
+        "SS" => {
+            let list: Vec<String> = as_array(value)?;
+            check_unique(list.len(), list.iter().unique().count())?;
+            Ok(build_string_set_attr(list))
+        }
+        // numbers of the same value are duplicates, e.g. 1.5 and 1.50
+        "NS" => {
+            let list: Vec<String> = as_array(value)?
+                .into_iter()
+                .map(as_number)
+                .collect::<Result<_, _>>()?;
+            let unique_count = list
+                .iter()
+                .map(|x| canonical_number(x).unwrap())
+                .unique()
+                .count();
+            check_unique(list.len(), unique_count)?;
+            Ok(build_number_set_attr(list))
+        }
+        "BS" => {
+            let list: Vec<Bytes> = as_array(value)?
+                .into_iter()
+                .map(as_binary)
+                .collect::<Result<_, _>>()?;
+            check_unique(list.len(), list.iter().unique().count())?;
+            Ok(build_binary_set_attr(list))
+        }
+        "L" => match value {
+            Value::Array(array) => Ok(build_list_attr(
+                array
+                    .iter()
+                    .map(parse_dynamodb_json)
+                    .collect::<Result<_, _>>()?,
+            )),
+            _ => Err(invalid()),
+        },
+        "M" => match value {
+            Value::Object(map) => Ok(build_map_attr(
+                map.iter()
+                    .map(|(k, v)| parse_dynamodb_json(v).map(|x| (k.to_owned(), x)))
+                    .collect::<Result<_, _>>()?,
+            )),
+            _ => Err(invalid()),
+        },
+        "NULL" => Err(invalid()),
+        _ => Err(format!("{} is not a type descriptor", descriptor)),
+    }
+}
+
+// timestamp normalized to iso8601 string or epoch number
+fn build_timestamp_attr(
+    timestamp: &TimestampFormat,
+    tokens: &Tokens,
+    text: String,
+) -> Result<AttributeValue, String> {
+    if tokens.is_null(&text) {
+        return Ok(build_null_attr());
+    }
+
+    let normalized = timestamp.normalize(&text)?;
+    match timestamp.output_type() {
+        ColumnType::N => Ok(build_number_attr(normalized)),
+        _ => Ok(build_string_attr(normalized)),
+    }
+}
+
+// decode text of a binary attribute, returns error if the text is not in the encoding
+fn decode_binary(text: &str, encoding: BinaryEncoding) -> Result<Bytes, String> {
+    let decoded = match encoding {
+        BinaryEncoding::Utf8 => return Ok(Bytes::from(text.to_string())),
+        BinaryEncoding::Base64 => base64::decode(text).ok(),
+        BinaryEncoding::Base64Url => base64::decode_config(text, base64::URL_SAFE).ok(),
+        BinaryEncoding::Hex => hex::decode(text).ok(),
+    };
+
+    match decoded {
+        Some(bytes) => Ok(Bytes::from(bytes)),
+        None => Err(format!("{} is not valid {}", text, encoding)),
+    }
+}
+
+// DynamoDB type of an attribute
+pub fn attr_type(attr: &AttributeValue) -> ColumnType {
+    if attr.s.is_some() {
+        ColumnType::S
+    } else if attr.n.is_some() {
+        ColumnType::N
+    } else if attr.b.is_some() {
+        ColumnType::B
+    } else if attr.bool.is_some() {
+        ColumnType::Bool
+    } else if attr.ss.is_some() {
+        ColumnType::SS
+    } else if attr.ns.is_some() {
+        ColumnType::NS
+    } else if attr.bs.is_some() {
+        ColumnType::BS
+    } else if attr.l.is_some() {
+        ColumnType::L
+    } else if attr.m.is_some() {
+        ColumnType::M
+    } else {
+        ColumnType::Null
+    }
+}
+
+// convert an attribute back into the text of a csv cell
+// this is the reverse of build_attr, so exported csv can be imported again
+// binaries are base64, the encoding annotated in header of exported csv
+pub fn attr_to_text(attr: &AttributeValue) -> String {
+    if let Some(s) = &attr.s {
+        s.to_owned()
+    } else if let Some(n) = &attr.n {
+        n.to_owned()
+    } else if let Some(b) = &attr.b {
+        base64::encode(b)
+    } else if let Some(b) = attr.bool {
+        b.to_string()
+    } else if attr.null.is_some() {
+        "null".to_string()
+    } else {
+        to_string(&attr_to_json(attr)).unwrap()
+    }
+}
+
+// convert an attribute into plain json (without DynamoDB type descriptors)
+fn attr_to_json(attr: &AttributeValue) -> Value {
+    if let Some(s) = &attr.s {
+        Value::String(s.to_owned())
+    } else if let Some(n) = &attr.n {
+        number_to_json(n)
+    } else if let Some(b) = &attr.b {
+        Value::String(base64::encode(b))
+    } else if let Some(b) = attr.bool {
+        Value::Bool(b)
+    } else if let Some(l) = &attr.l {
+        Value::Array(l.iter().map(attr_to_json).collect())
+    } else if let Some(m) = &attr.m {
+        Value::Object(
+            m.iter()
+                .map(|(k, v)| (k.to_owned(), attr_to_json(v)))
+                .collect(),
+        )
+    } else if let Some(ss) = &attr.ss {
+        Value::Array(ss.iter().map(|x| Value::String(x.to_owned())).collect())
+    } else if let Some(ns) = &attr.ns {
+        Value::Array(ns.iter().map(|x| number_to_json(x)).collect())
+    } else if let Some(bs) = &attr.bs {
+        Value::Array(
+            bs.iter()
+                .map(|x| Value::String(base64::encode(x)))
+                .collect(),
+        )
+    } else {
+        Value::Null
+    }
+}
+
+// convert an attribute into DynamoDB JSON, the reverse of parse_dynamodb_json
+// e.g. {"M": {"blob": {"B": "aGk="}, "tags": {"SS": ["a"]}}}, so exact types are kept
+pub fn attr_to_dynamodb_json(attr: &AttributeValue) -> Value {
+    let strings = |list: Vec<String>| Value::Array(list.into_iter().map(Value::String).collect());
+    let (descriptor, value) = if let Some(s) = &attr.s {
+        ("S", Value::String(s.to_owned()))
+    } else if let Some(n) = &attr.n {
+        ("N", Value::String(n.to_owned()))
+    } else if let Some(b) = &attr.b {
+        ("B", Value::String(base64::encode(b)))
+    } else if let Some(b) = attr.bool {
+        ("BOOL", Value::Bool(b))
+    } else if let Some(ss) = &attr.ss {
+        ("SS", strings(ss.to_owned()))
+    } else if let Some(ns) = &attr.ns {
+        ("NS", strings(ns.to_owned()))
+    } else if let Some(bs) = &attr.bs {
+        ("BS", strings(bs.iter().map(base64::encode).collect()))
+    } else if let Some(l) = &attr.l {
+        (
+            "L",
+            Value::Array(l.iter().map(attr_to_dynamodb_json).collect()),
+        )
+    } else if let Some(m) = &attr.m {
+        (
+            "M",
+            Value::Object(
+                m.iter()
+                    .map(|(k, v)| (k.to_owned(), attr_to_dynamodb_json(v)))
+                    .collect(),
+            ),
+        )
+    } else {
+        ("NULL", Value::Bool(true))
+    };
+
+    Value::Object(vec![(descriptor.to_string(), value)].into_iter().collect())
+}
+
+// a number which cannot be represented in json is kept as string
+fn number_to_json(text: &str) -> Value {
+    match from_str::<Value>(text) {
+        Ok(Value::Number(x)) => Value::Number(x),
+        _ => Value::String(text.to_string()),
+    }
+}
+
+// parse text as a json array, for list and set types
+fn parse_json_array(text: &str) -> Result<Vec<Value>, String> {
+    match from_str::<Value>(text) {
+        Ok(Value::Array(list)) => Ok(list),
+        _ => Err(format!("{} is not a valid json array", text)),
     }
 }
 
 // check if all itmes in the list is unique string
-fn is_string_set(list: &Vec<Value>) -> bool {
+fn is_string_set(list: &[Value]) -> bool {
     let str_list: Vec<_> = list.iter().map(|x| x.as_str()).collect();
     str_list.iter().all(|x| x.is_some()) && list.len() == str_list.iter().unique().count()
 }
 
 // check if all itmes in the list is unique number
-fn is_number_set(list: &Vec<Value>) -> bool {
-    let f64_list: Vec<_> = list.iter().map(|x| x.as_f64()).collect();
-    if f64_list.iter().all(|x| x.is_some()) {
-        let num_as_string_list: Vec<_> = list
+// numbers of the same value are duplicates, e.g. 1.5 and 1.50
+fn is_number_set(list: &[Value]) -> bool {
+    if list.iter().all(|x| x.is_number()) {
+        let canonical_list: Vec<_> = list
             .iter()
-            .map(|x| to_string(x).unwrap_or_default())
+            .map(|x| canonical_number(&to_string(x).unwrap()).ok())
             .collect();
-        list.len() == num_as_string_list.iter().unique().count()
+        canonical_list.iter().all(|x| x.is_some())
+            && list.len() == canonical_list.iter().unique().count()
     } else {
         false
     }
 }
 
+// check if all itmes in the list is unique binary in the given encoding
+fn is_binary_set(list: &[Value], encoding: BinaryEncoding) -> bool {
+    let bytes_list: Vec<_> = list
+        .iter()
+        .map(|x| x.as_str().and_then(|x| decode_binary(x, encoding).ok()))
+        .collect();
+    bytes_list.iter().all(|x| x.is_some()) && list.len() == bytes_list.iter().unique().count()
+}
+
+// number whose type is inferred rather than declared
+// if DynamoDB cannot store it exactly, it is saved as string with a warning
+fn build_inferred_number_attr(text: String) -> AttributeValue {
+    match check_number(&text) {
+        Ok(_) => build_number_attr(text),
+        Err(error) => {
+            println!("Warning: {} {}. Saved as string instead", text, error);
+            build_string_attr(text)
+        }
+    }
+}
+
 // null type in dynamodb
 fn build_null_attr() -> AttributeValue {
     AttributeValue {
@@ -192,7 +908,7 @@ fn build_bytes_attr(b: Bytes) -> AttributeValue {
     }
 }
 
-fn build_list_attr(list: Vec<AttributeValue>) -> AttributeValue {
+pub fn build_list_attr(list: Vec<AttributeValue>) -> AttributeValue {
     AttributeValue {
         l: Some(list),
         ..Default::default()
@@ -213,9 +929,478 @@ fn build_number_set_attr(list: Vec<String>) -> AttributeValue {
     }
 }
 
-fn build_map_attr(map: HashMap<String, AttributeValue>) -> AttributeValue {
+fn build_binary_set_attr(list: Vec<Bytes>) -> AttributeValue {
+    AttributeValue {
+        bs: Some(list),
+        ..Default::default()
+    }
+}
+
+pub fn build_map_attr(map: HashMap<String, AttributeValue>) -> AttributeValue {
     AttributeValue {
         m: Some(map),
         ..Default::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser(schema: &str, empty_policy: Option<EmptyPolicy>) -> Parser {
+        Parser {
+            allow_set: false,
+            allow_null: false,
+            empty_policy,
+            detect_dynamodb_json: false,
+            schema: from_str(schema).unwrap(),
+        }
+    }
+
+    fn build(
+        parser: &Parser,
+        column_name: &str,
+        key_type: Option<&str>,
+        text: &str,
+    ) -> Result<Option<AttributeValue>, String> {
+        let key_type = key_type.map(|x| x.to_string());
+        parser.build_cell_attr(column_name, key_type.as_ref(), text.to_string())
+    }
+
+    #[test]
+    fn empty_policy_of_non_key_columns() {
+        let omit = parser("{}", Some(EmptyPolicy::Omit));
+        assert_eq!(build(&omit, "a", None, ""), Ok(None));
+
+        let null = parser("{}", Some(EmptyPolicy::Null));
+        assert_eq!(build(&null, "a", None, ""), Ok(Some(build_null_attr())));
+
+        let string = parser(r#"{"columns": {"a": "N"}}"#, Some(EmptyPolicy::String));
+        assert_eq!(
+            build(&string, "a", None, ""),
+            Ok(Some(build_string_attr(String::new())))
+        );
+
+        let reject = parser("{}", Some(EmptyPolicy::Reject));
+        assert!(build(&reject, "a", None, "").is_err());
+
+        // without policy, empty is inferred as string, and fails declared types
+        let none = parser(r#"{"columns": {"n": "N"}}"#, None);
+        assert_eq!(
+            build(&none, "a", None, ""),
+            Ok(Some(build_string_attr(String::new())))
+        );
+        assert!(build(&none, "n", None, "").is_err());
+    }
+
+    #[test]
+    fn empty_policy_of_key_columns() {
+        for key_type in ["S", "N", "B"] {
+            let omit = parser("{}", Some(EmptyPolicy::Omit));
+            assert_eq!(build(&omit, "k", Some(key_type), ""), Ok(None));
+
+            // null of a key is left out, e.g. the item is not in a sparse index
+            let null = parser("{}", Some(EmptyPolicy::Null));
+            assert_eq!(build(&null, "k", Some(key_type), ""), Ok(None));
+
+            let string = parser("{}", Some(EmptyPolicy::String));
+            assert!(build(&string, "k", Some(key_type), "").is_err());
+
+            let reject = parser("{}", Some(EmptyPolicy::Reject));
+            assert!(build(&reject, "k", Some(key_type), "").is_err());
+
+            let none = parser("{}", None);
+            assert!(build(&none, "k", Some(key_type), "").is_err());
+        }
+
+        // policy of the column overrides the global one
+        let column = parser(
+            r#"{"columns": {"k": {"type": "S", "empty": "null"}}}"#,
+            Some(EmptyPolicy::String),
+        );
+        assert_eq!(build(&column, "k", Some("S"), ""), Ok(None));
+        assert!(build(&column, "other", Some("S"), "").is_err());
+    }
+
+    #[test]
+    fn key_attributes_are_checked() {
+        let typed = parser(r#"{"columns": {"s": "S", "n": "N"}}"#, None);
+        let cannot_be_empty = |key_type| {
+            Err(format!(
+                "key attribute of type {} cannot be empty",
+                key_type
+            ))
+        };
+
+        // digits of numeric keys are kept
+        assert_eq!(
+            build(&typed, "id", Some("N"), "1.50"),
+            Ok(Some(build_number_attr("1.50".to_string())))
+        );
+        assert_eq!(
+            build(&typed, "id", Some("N"), "abc"),
+            Err("abc is not a valid number".to_string())
+        );
+        assert!(build(&typed, "id", Some("N"), "1e400").is_err());
+
+        // declared type must be the key type
+        assert_eq!(
+            build(&typed, "s", Some("N"), "1"),
+            Err("1 is S rather than key type N".to_string())
+        );
+        assert!(build(&typed, "n", Some("N"), "1").is_ok());
+
+        let string = parser("{}", Some(EmptyPolicy::String));
+        assert_eq!(build(&string, "id", Some("N"), ""), cannot_be_empty("N"));
+        assert_eq!(build(&string, "id", Some("S"), ""), cannot_be_empty("S"));
+        assert_eq!(build(&typed, "id", Some("S"), ""), cannot_be_empty("S"));
+
+        // null of a key is left out
+        assert_eq!(build(&typed, "n", Some("N"), "null"), Ok(None));
+    }
+
+    fn numbers(list: &[&str]) -> Vec<AttributeValue> {
+        list.iter()
+            .map(|x| build_number_attr(x.to_string()))
+            .collect()
+    }
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn split_numbers_are_typed_like_cells() {
+        let parser = parser(
+            r#"{"columns": {
+                "list": {"type": "L", "split": {"delimiter": "|", "element": "N"}},
+                "set": {"type": "NS", "split": {"delimiter": "|", "element": "N"}},
+                "plain": "N"
+            }}"#,
+            None,
+        );
+
+        // digits are kept as they are, like a cell of N
+        for text in ["007", "+5", ".5"] {
+            assert_eq!(
+                build(&parser, "plain", None, text),
+                Ok(Some(build_number_attr(text.to_string())))
+            );
+        }
+        assert_eq!(
+            build(&parser, "list", None, "007|+5|.5"),
+            Ok(Some(build_list_attr(numbers(&["007", "+5", ".5"]))))
+        );
+        assert_eq!(
+            build(&parser, "set", None, "007|+5|.5"),
+            Ok(Some(build_number_set_attr(strings(&["007", "+5", ".5"]))))
+        );
+
+        assert_eq!(
+            build(&parser, "list", None, "1|x"),
+            Err("x is not a valid number".to_string())
+        );
+        assert!(build(&parser, "list", None, "1|1e400").is_err());
+
+        // numbers of the same value are duplicates
+        assert!(build(&parser, "set", None, "1.5|1.50").is_err());
+    }
+
+    #[test]
+    fn split_strings_and_inferred_elements() {
+        let parser = parser(
+            r#"{"columns": {
+                "strings": {"type": "SS", "split": {"delimiter": "|", "element": "S"}},
+                "inferred": {"type": "L", "split": {"delimiter": ",", "trim": true}},
+                "raw": {"type": "L", "split": {"delimiter": ",", "trim": false, "element": "S"}}
+            }}"#,
+            None,
+        );
+
+        assert_eq!(
+            build(&parser, "strings", None, "007|a"),
+            Ok(Some(build_string_set_attr(strings(&["007", "a"]))))
+        );
+        assert!(build(&parser, "strings", None, "a|a").is_err());
+
+        // an element is a number if a cell of the same text would be
+        assert_eq!(
+            build(&parser, "inferred", None, "007, 1 ,a"),
+            Ok(Some(build_list_attr(vec![
+                build_number_attr("007".to_string()),
+                build_number_attr("1".to_string()),
+                build_string_attr("a".to_string()),
+            ])))
+        );
+        assert_eq!(
+            build(&parser, "raw", None, " a,b"),
+            Ok(Some(build_list_attr(vec![
+                build_string_attr(" a".to_string()),
+                build_string_attr("b".to_string()),
+            ])))
+        );
+    }
+
+    #[test]
+    fn split_drops_empty_elements() {
+        let parser = parser(
+            r#"{"columns": {
+                "list": {"type": "L", "split": {"delimiter": "|"}},
+                "set": {"type": "SS", "split": {"delimiter": "|"}}
+            }}"#,
+            None,
+        );
+
+        assert_eq!(
+            build(&parser, "set", None, "a||b| "),
+            Ok(Some(build_string_set_attr(strings(&["a", "b"]))))
+        );
+        assert_eq!(
+            build(&parser, "list", None, "| |"),
+            Ok(Some(build_list_attr(Vec::new())))
+        );
+
+        // a set cannot be empty
+        assert!(build(&parser, "set", None, "| |").is_err());
+    }
+
+    #[test]
+    fn missing_cells_are_null_whatever_the_type() {
+        let mut parser = parser(r#"{"columns": {"s": "S", "b": "B"}}"#, None);
+        for column_name in ["s", "b", "other"] {
+            assert_eq!(
+                parser.build_row_cell_attr(column_name, None, None),
+                Ok(None)
+            );
+        }
+        let key_type = "S".to_string();
+        assert_eq!(
+            parser.build_row_cell_attr("s", Some(&key_type), None),
+            Ok(None)
+        );
+
+        parser.allow_null = true;
+        assert_eq!(
+            parser.build_row_cell_attr("s", None, None),
+            Ok(Some(build_null_attr()))
+        );
+        assert_eq!(
+            parser.build_row_cell_attr("s", Some(&key_type), None),
+            Ok(None)
+        );
+
+        // any text is a cell, including a NUL character
+        assert_eq!(
+            parser.build_row_cell_attr("s", None, Some("\u{0}".to_string())),
+            Ok(Some(build_string_attr("\u{0}".to_string())))
+        );
+    }
+
+    #[test]
+    fn dynamodb_json_of_attributes_is_imported_as_it_is() {
+        let mut parser = parser("{}", None);
+        parser.detect_dynamodb_json = true;
+        let nested = build_map_attr(
+            vec![
+                ("blob".to_string(), build_bytes_attr(Bytes::from("hi"))),
+                ("tags".to_string(), build_string_set_attr(strings(&["a"]))),
+                (
+                    "list".to_string(),
+                    build_list_attr(vec![
+                        build_number_attr("1.50".to_string()),
+                        build_binary_set_attr(vec![Bytes::from("x"), Bytes::from("y")]),
+                        build_string_attr(String::new()),
+                    ]),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        for attr in [
+            nested,
+            build_string_attr("123".to_string()),
+            build_string_attr(String::new()),
+            build_bool_attr(true),
+            build_number_set_attr(strings(&["1", "1.5"])),
+        ] {
+            let text = to_string(&attr_to_dynamodb_json(&attr)).unwrap();
+            assert_eq!(
+                build(&parser, "a", None, &text),
+                Ok(Some(attr.clone())),
+                "{}",
+                text
+            );
+        }
+    }
+
+    fn parse(text: &str) -> Result<AttributeValue, String> {
+        parse_dynamodb_json(&from_str(text).unwrap())
+    }
+
+    #[test]
+    fn dynamodb_json_has_one_type_descriptor_per_value() {
+        assert_eq!(
+            parse(r#"{"N": "1.50"}"#),
+            Ok(build_number_attr("1.50".to_string()))
+        );
+        assert_eq!(
+            parse(r#"{"L": [{"S": "a"}, {"BOOL": false}, {"NULL": true}]}"#),
+            Ok(build_list_attr(vec![
+                build_string_attr("a".to_string()),
+                build_bool_attr(false),
+                build_null_attr(),
+            ]))
+        );
+
+        for text in [r#"{}"#, r#"{"S": "a", "N": "1"}"#, r#""a""#, r#"["a"]"#] {
+            assert_eq!(
+                parse(text),
+                Err("expected an object of one type descriptor".to_string()),
+                "{}",
+                text
+            );
+        }
+        assert_eq!(
+            parse(r#"{"M": {"a": {"S": "x", "N": "1"}}}"#),
+            Err("expected an object of one type descriptor".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"X": "a"}"#),
+            Err("X is not a type descriptor".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"s": "a"}"#),
+            Err("s is not a type descriptor".to_string())
+        );
+
+        for text in [
+            r#"{"S": 1}"#,
+            r#"{"N": 1}"#,
+            r#"{"BOOL": "true"}"#,
+            r#"{"NULL": false}"#,
+            r#"{"L": {}}"#,
+            r#"{"M": []}"#,
+            r#"{"SS": "a"}"#,
+        ] {
+            let descriptor = from_str::<Value>(text)
+                .unwrap()
+                .as_object()
+                .unwrap()
+                .keys()
+                .next()
+                .unwrap()
+                .to_owned();
+            assert_eq!(
+                parse(text),
+                Err(format!("invalid value of {}", descriptor)),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn dynamodb_json_sets_are_non_empty_without_duplicates() {
+        assert_eq!(
+            parse(r#"{"SS": ["a", "b"]}"#),
+            Ok(build_string_set_attr(strings(&["a", "b"])))
+        );
+        assert_eq!(
+            parse(r#"{"SS": []}"#),
+            Err("SS cannot be empty".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"NS": []}"#),
+            Err("NS cannot be empty".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"BS": []}"#),
+            Err("BS cannot be empty".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"SS": ["a", "a"]}"#),
+            Err("SS has duplicates".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"SS": ["a", 1]}"#),
+            Err("invalid value of SS".to_string())
+        );
+
+        // numbers of the same value are duplicates, binaries are compared decoded
+        assert_eq!(
+            parse(r#"{"NS": ["1.5", "1.50"]}"#),
+            Err("NS has duplicates".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"BS": ["aGk=", "aGk="]}"#),
+            Err("BS has duplicates".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"NS": ["1", "1.5"]}"#),
+            Ok(build_number_set_attr(strings(&["1", "1.5"])))
+        );
+    }
+
+    #[test]
+    fn dynamodb_json_numbers_and_binaries_must_be_valid() {
+        assert_eq!(
+            parse(r#"{"N": "abc"}"#),
+            Err("abc is not a valid number".to_string())
+        );
+        assert!(parse(r#"{"N": "1e400"}"#).is_err());
+        assert!(parse(&format!(r#"{{"N": "{}"}}"#, "1".repeat(39))).is_err());
+        assert_eq!(
+            parse(r#"{"NS": ["1", "x"]}"#),
+            Err("x is not a valid number".to_string())
+        );
+
+        assert_eq!(
+            parse(r#"{"B": "aGk="}"#),
+            Ok(build_bytes_attr(Bytes::from("hi")))
+        );
+        assert_eq!(
+            parse(r#"{"B": "not base64!"}"#),
+            Err("not base64! is not valid base64".to_string())
+        );
+        assert_eq!(
+            parse(r#"{"BS": ["aGk=", "%%"]}"#),
+            Err("%% is not valid base64".to_string())
+        );
+    }
+
+    #[test]
+    fn dynamodb_json_cells_of_columns() {
+        let parser = parser(
+            r#"{"columns": {"d": {"dynamodb_json": true, "tokens": {"null": ["N/A"]}}}}"#,
+            None,
+        );
+
+        // words of null still save null, left out without --allownull
+        assert_eq!(build(&parser, "d", None, "n/a"), Ok(None));
+        assert_eq!(build(&parser, "d", None, r#"{"NULL": true}"#), Ok(None));
+        assert_eq!(
+            build(&parser, "d", None, r#"{"S": "null"}"#),
+            Ok(Some(build_string_attr("null".to_string())))
+        );
+
+        assert_eq!(
+            build(&parser, "d", None, r#"{"S": "a""#),
+            Err(r#"{"S": "a" is not valid DynamoDB JSON. invalid json"#.to_string())
+        );
+        assert_eq!(
+            build(&parser, "d", None, r#"{"SS": []}"#),
+            Err(r#"{"SS": []} is not valid DynamoDB JSON. SS cannot be empty"#.to_string())
+        );
+
+        // a key must be of the key type
+        assert_eq!(
+            build(&parser, "d", Some("N"), r#"{"S": "1"}"#),
+            Err(r#"{"S": "1"} is not of key type N"#.to_string())
+        );
+        assert_eq!(
+            build(&parser, "d", Some("N"), r#"{"N": "1"}"#),
+            Ok(Some(build_number_attr("1".to_string())))
+        );
+    }
+}
@@ -0,0 +1,819 @@
+use super::error::Error;
+use super::timestamp::TimestampFormat;
+use itertools::Itertools;
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Visitor},
+    Deserialize, Serialize,
+};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fmt, fs,
+    str::FromStr,
+};
+
+// DynamoDB type of a column, declared by user instead of inferred
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(try_from = "String")]
+pub enum ColumnType {
+    S,
+    N,
+    B,
+    Bool,
+    Null,
+    SS,
+    NS,
+    BS,
+    L,
+    M,
+
+    // raw json, converted the same way as inferred json values
+    Json,
+
+    // the column is dropped
+    Skip,
+}
+
+// how json arrays in a column are converted, including nested arrays
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum ArrayMode {
+    // set if possible (not empty, no duplicates), otherwise list. Same as --allowset
+    // binary set is never chosen automatically
+    #[serde(rename = "auto")]
+    Auto,
+
+    #[serde(rename = "L")]
+    List,
+
+    #[serde(rename = "SS")]
+    StringSet,
+
+    #[serde(rename = "NS")]
+    NumberSet,
+
+    // elements are base64 strings
+    #[serde(rename = "BS")]
+    BinarySet,
+}
+
+// encoding of binary text, for B and BS
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryEncoding {
+    // bytes of the text itself
+    Utf8,
+    Base64,
+    Base64Url,
+    Hex,
+}
+
+// what to do with an empty cell
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyPolicy {
+    // leave the attribute out of the item, e.g. sparse index keys
+    Omit,
+
+    // save as null type, even without --allownull
+    Null,
+
+    // save as empty string, whatever the type of the column
+    String,
+
+    // the row is saved to failed items
+    Reject,
+}
+
+// words of true, false and null in cells, e.g. {"true": ["Y", "Yes"], "null": ["\\N", "N/A"]}
+// declared words are matched case-insensitively, they replace the default (lowercase) word
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tokens {
+    #[serde(rename = "true", skip_serializing_if = "Option::is_none")]
+    pub true_tokens: Option<Vec<String>>,
+
+    #[serde(rename = "false", skip_serializing_if = "Option::is_none")]
+    pub false_tokens: Option<Vec<String>>,
+
+    #[serde(rename = "null", skip_serializing_if = "Option::is_none")]
+    pub null_tokens: Option<Vec<String>>,
+}
+
+/*
+    rules a cell must follow, otherwise the row is rejected, e.g.
+    {"required": true, "pattern": "[A-Z]{2}[0-9]+", "min": 0, "max": 100, "max_length": 20,
+     "allowed": ["active", "closed"], "json_schema": {"type": "object", "required": ["id"]}}
+    empty and null cells only violate required
+*/
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidationRule {
+    #[serde(default)]
+    pub required: bool,
+
+    // regex which must match the whole cell
+    pub pattern: Option<String>,
+
+    // inclusive range, the cell must be a number
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+
+    // in characters
+    pub max_length: Option<usize>,
+
+    pub allowed: Option<Vec<String>>,
+
+    // the cell must be json which is valid against the schema
+    pub json_schema: Option<Value>,
+}
+
+// split a delimited cell, e.g. red|green|blue, into an array
+// the array is converted by the type of the column (L, SS, NS, BS) or its array mode
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SplitRule {
+    pub delimiter: String,
+
+    // remove whitespace around each element, empty elements are always dropped
+    #[serde(default = "default_trim")]
+    pub trim: bool,
+
+    // inferred if not declared: number if possible, otherwise string
+    pub element: Option<ElementType>,
+}
+
+// type of elements split from a cell
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum ElementType {
+    S,
+    N,
+}
+
+// one of the items built from each row, e.g. an order and an edge from customer to order
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemDefinition {
+    // shown in messages, index of the item if not declared
+    pub name: Option<String>,
+
+    // computed attributes of this item only, usually keys
+    #[serde(default)]
+    pub computed: HashMap<String, String>,
+
+    // columns (after mapping) and shared computed attributes saved in this item
+    // all of them if not declared
+    pub attributes: Option<Vec<String>>,
+}
+
+/*
+    merge rows sharing the same values of key columns into one item (fan-in), e.g.
+    {
+        "by": ["order_id"],
+        "collect": {"lines": {"columns": ["sku", "qty"]}, "skus": {"columns": ["sku"], "type": "SS"}},
+        "aggregate": {"total": {"function": "sum", "column": "price"}, "line_count": {"function": "count"}}
+    }
+*/
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupRule {
+    pub by: Vec<String>,
+
+    #[serde(default)]
+    pub collect: HashMap<String, CollectRule>,
+
+    #[serde(default)]
+    pub aggregate: HashMap<String, AggregateRule>,
+}
+
+// values of columns in all rows of a group
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CollectRule {
+    pub columns: Vec<String>,
+
+    // L of M (one map per row) by default, or a set of a single column (SS, NS, BS)
+    #[serde(rename = "type")]
+    pub collect_type: Option<ColumnType>,
+}
+
+// a number computed from all rows of a group
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AggregateRule {
+    pub function: AggregateFunction,
+
+    // required except for count, which counts rows if no column is given
+    pub column: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregateFunction {
+    Sum,
+    Count,
+    Min,
+    Max,
+}
+
+/*
+    a reference csv joined on a column, e.g. country_name from country_code
+    {"file": "countries.csv", "on": "country_code", "key": "code", "columns": ["country_name"]}
+*/
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LookupRule {
+    pub file: String,
+
+    // column in csv (after mapping)
+    pub on: String,
+
+    // column in lookup file, same as on if not declared
+    pub key: Option<String>,
+
+    // columns brought in, all columns except key if not declared
+    pub columns: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub join: JoinType,
+
+    // only for left join
+    #[serde(default)]
+    pub missing: MissingPolicy,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinType {
+    // rows without match are dropped
+    Inner,
+
+    // rows without match are kept, see missing policy
+    #[default]
+    Left,
+}
+
+// what to do with a row without match in left join
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingPolicy {
+    // columns brought in are null
+    #[default]
+    Null,
+
+    // the row is saved to failed items
+    Fail,
+}
+
+/*
+    a column is declared either by its type only:
+        "Zip": "S"
+    or by a full definition:
+        "Zip": {"type": "S"}
+*/
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnSchema {
+    #[serde(rename = "type")]
+    pub column_type: Option<ColumnType>,
+
+    // overrides --allowset for this column
+    pub array: Option<ArrayMode>,
+
+    // for B and BS, including key attributes of type B
+    pub encoding: Option<BinaryEncoding>,
+
+    // parse the column as timestamp, type is decided by output format
+    pub timestamp: Option<TimestampFormat>,
+
+    // overrides --empty for this column
+    pub empty: Option<EmptyPolicy>,
+
+    // the cell is a delimited list instead of a json array
+    pub split: Option<SplitRule>,
+
+    // each set of words declared here overrides the global one
+    #[serde(default)]
+    pub tokens: Tokens,
+
+    // cells are DynamoDB JSON, e.g. {"SS": ["a", "b"]}, saved as they are
+    #[serde(default)]
+    pub dynamodb_json: bool,
+
+    // checked before the row is built into items
+    pub validate: Option<ValidationRule>,
+}
+
+// accepts both forms of column declaration
+struct ColumnDefinition(ColumnSchema);
+
+/*
+    schema file, in json or toml
+    columns which are not listed fall back to type inference
+    example:
+    {
+        "columns": {
+            "Zip": "S",
+            "Phone": {"type": "S"},
+            "Scores": {"type": "M", "array": "NS"},
+            "Avatar": {"type": "B", "encoding": "base64"},
+            "CreatedAt": {"timestamp": {"format": "%d/%m/%Y %H:%M", "timezone": "+10:00"}},
+            "GsiKey": {"type": "S", "empty": "omit"},
+            "Colors": {"type": "SS", "split": {"delimiter": "|"}},
+            "Active": {"tokens": {"true": ["Y"], "false": ["N"]}},
+            "Payload": {"dynamodb_json": true},
+            "Status": {"validate": {"required": true, "allowed": ["active", "closed"]}},
+            "RowNo": "skip"
+        },
+        "rename": {"user_id": "pk"},
+        "drop": ["row_no"],
+        "computed": {"SK": "ORDER#{order_date|date:%Y%m%d}#{order_id|pad:8}"},
+        "items": [
+            {"name": "order", "computed": {"PK": "ORDER#{order_id}"}},
+            {"name": "edge", "computed": {"PK": "CUSTOMER#{customer_id}"}, "attributes": ["SK"]}
+        ],
+        "group": {"by": ["order_id"], "aggregate": {"total": {"function": "sum", "column": "price"}}},
+        "lookups": [{"file": "countries.csv", "on": "country_code", "join": "inner"}],
+        "tokens": {"true": ["true", "yes", "1"], "false": ["false", "no", "0"], "null": ["null", "\\N"]}
+    }
+*/
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    #[serde(default, deserialize_with = "deserialize_columns")]
+    pub columns: HashMap<String, ColumnSchema>,
+
+    // header name -> attribute name, applied before typing
+    #[serde(default)]
+    pub rename: HashMap<String, String>,
+
+    // columns which are not saved, same as skip type
+    #[serde(default)]
+    pub drop: Vec<String>,
+
+    // attribute name -> template of other columns (after mapping)
+    // computed attributes are typed like columns, e.g. by key type or columns in schema
+    #[serde(default)]
+    pub computed: HashMap<String, String>,
+
+    // several items built from each row (fan-out), one item of all columns if empty
+    #[serde(default)]
+    pub items: Vec<ItemDefinition>,
+
+    // several rows merged into each item (fan-in)
+    pub group: Option<GroupRule>,
+
+    // columns brought in from other csv files, added to the end of header
+    #[serde(default)]
+    pub lookups: Vec<LookupRule>,
+
+    // words of true, false and null in all columns
+    #[serde(default)]
+    pub tokens: Tokens,
+}
+
+impl Schema {
+    pub fn column(&self, column_name: &str) -> Option<&ColumnSchema> {
+        self.columns.get(column_name)
+    }
+
+    // type declared in schema, or output type of timestamp
+    pub fn column_type(&self, column_name: &str) -> Option<ColumnType> {
+        let column_schema = self.column(column_name)?;
+        match (&column_schema.column_type, &column_schema.timestamp) {
+            (Some(ColumnType::Skip), _) => Some(ColumnType::Skip),
+            (_, Some(timestamp)) => Some(timestamp.output_type()),
+            (column_type, None) => *column_type,
+        }
+    }
+
+    // words of a column, each set declared for the column comes first, then global sets
+    pub fn column_tokens(&self, column_name: &str) -> Tokens {
+        let column_tokens = self.column(column_name).map(|x| &x.tokens);
+        let pick = |get: fn(&Tokens) -> &Option<Vec<String>>| {
+            column_tokens
+                .and_then(|x| get(x).clone())
+                .or_else(|| get(&self.tokens).clone())
+        };
+
+        Tokens {
+            true_tokens: pick(|x| &x.true_tokens),
+            false_tokens: pick(|x| &x.false_tokens),
+            null_tokens: pick(|x| &x.null_tokens),
+        }
+    }
+
+    // a word cannot mean two things, in any column
+    pub fn check_tokens(&self) -> Result<(), String> {
+        let column_names = self.columns.keys().map(|x| x.as_str());
+        for column_name in std::iter::once("").chain(column_names) {
+            let tokens = self.column_tokens(column_name);
+            let sets = [
+                &tokens.true_tokens,
+                &tokens.false_tokens,
+                &tokens.null_tokens,
+            ];
+            let mut words = HashSet::new();
+            for set in sets.iter() {
+                for word in set.iter().flatten().map(|x| x.to_lowercase()).unique() {
+                    if !words.insert(word.to_owned()) {
+                        return Err(format!(
+                            "{} is declared as more than one of true, false and null",
+                            word
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // rename and drop columns, after annotations are stripped
+    // a renamed column can be declared by either name in schema, the new name comes first
+    pub fn apply_mapping(&mut self, column_names: &[String]) -> Result<Vec<String>, String> {
+        for column_name in self.rename.keys().chain(&self.drop) {
+            if !column_names.contains(column_name) {
+                return Err(format!("Column {} is not found in header", column_name));
+            }
+        }
+
+        let mapped_names: Vec<_> = column_names
+            .iter()
+            .map(|x| match self.rename.get(x) {
+                Some(new_name) if !self.drop.contains(x) => new_name.to_owned(),
+                _ => x.to_owned(),
+            })
+            .collect();
+
+        for (column_name, new_name) in column_names.iter().zip(&mapped_names) {
+            if self.drop.contains(column_name) {
+                self.columns
+                    .entry(new_name.to_owned())
+                    .or_default()
+                    .column_type = Some(ColumnType::Skip);
+            } else if new_name != column_name {
+                if let Some(column_schema) = self.columns.get(column_name).cloned() {
+                    self.columns
+                        .entry(new_name.to_owned())
+                        .or_insert(column_schema);
+                }
+            } else {
+                continue;
+            }
+
+            // a mapped column must not be mixed up with another column
+            if mapped_names.iter().filter(|x| *x == new_name).count() > 1 {
+                return Err(format!(
+                    "Column {} is mapped to {}, which is the name of another column",
+                    column_name, new_name
+                ));
+            }
+        }
+
+        Ok(mapped_names)
+    }
+
+    // a column of DynamoDB JSON has its own types
+    fn check_dynamodb_json(&self) -> Result<(), String> {
+        for (column_name, column_schema) in &self.columns {
+            if column_schema.dynamodb_json
+                && (column_schema.column_type.is_some()
+                    || column_schema.timestamp.is_some()
+                    || column_schema.split.is_some())
+            {
+                return Err(format!(
+                    "Column {} of DynamoDB JSON cannot have type, timestamp or split",
+                    column_name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // a split column must be converted to an array
+    fn check_split_rules(&self) -> Result<(), String> {
+        for (column_name, column_schema) in &self.columns {
+            if let Some(split) = &column_schema.split {
+                if split.delimiter.is_empty() {
+                    return Err(format!("Column {} has an empty delimiter", column_name));
+                }
+                match (column_schema.column_type, &column_schema.timestamp) {
+                    (_, Some(_)) => {
+                        return Err(format!(
+                            "Column {} cannot be both split and timestamp",
+                            column_name
+                        ))
+                    }
+                    (None, _)
+                    | (Some(ColumnType::L), _)
+                    | (Some(ColumnType::SS), _)
+                    | (Some(ColumnType::NS), _)
+                    | (Some(ColumnType::BS), _)
+                    | (Some(ColumnType::Skip), _) => {}
+                    (Some(column_type), _) => {
+                        return Err(format!(
+                            "Column {} is split, so its type must be L, SS, NS or BS instead of {}",
+                            column_name, column_type
+                        ))
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // strip type annotations from header (e.g. Count:N, Tags:SS, Blob:B:hex) and add them to schema
+    // annotations are case sensitive, a suffix which is not exactly a type
+    // (e.g. Ratio:n) is kept as part of the name
+    pub fn apply_header_annotations(&mut self, header: &[String]) -> Result<Vec<String>, String> {
+        let mut column_names = Vec::new();
+
+        for column in header {
+            match parse_annotation(column) {
+                Some((column_name, column_type, encoding)) => {
+                    let column_schema = self.columns.entry(column_name.to_string()).or_default();
+                    match column_schema.column_type {
+                        Some(declared_type) if declared_type != column_type => {
+                            return Err(format!(
+                                "Column {} is annotated as {}, but declared as {} in schema",
+                                column_name, column_type, declared_type
+                            ));
+                        }
+                        _ => column_schema.column_type = Some(column_type),
+                    }
+                    if encoding.is_some() {
+                        column_schema.encoding = encoding;
+                    }
+                    column_names.push(column_name.to_string());
+                }
+                None => column_names.push(column.to_owned()),
+            }
+        }
+
+        Ok(column_names)
+    }
+
+    // declared types must agree with key attributes of the table (including indexes),
+    // otherwise every batch containing the column would fail
+    // a skipped key of an index only leaves the item out of the index,
+    // keys of the table itself are checked by check_key_columns
+    pub fn check_key_types(&self, table_attrs: &HashMap<String, String>) -> Result<(), String> {
+        for (attr_name, attr_type) in table_attrs {
+            if let Some(column_type) = self.column_type(attr_name) {
+                if column_type != ColumnType::Skip && column_type.to_string() != *attr_type {
+                    return Err(format!(
+                        "Column {} is declared as {}, but it is a key attribute of type {}",
+                        attr_name, column_type, attr_type
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// read schema from a json or toml file (decided by extension)
+pub fn load_schema(filename: &str) -> Result<Schema, Error> {
+    let text = read_schema_file(filename)?;
+
+    let schema = if filename.to_lowercase().ends_with(".toml") {
+        toml::from_str(&text).map_err(|x| x.to_string())
+    } else {
+        serde_json::from_str(&text).map_err(|x| x.to_string())
+    };
+
+    schema
+        .and_then(|schema: Schema| schema.check_split_rules().map(|_| schema))
+        .and_then(|schema| schema.check_dynamodb_json().map(|_| schema))
+        .map_err(|error| Error::Config(format!("Invalid schema file {}. {}", filename, error)))
+}
+
+// schema file as it is, so options can be kept when types are added to it
+pub fn load_schema_value(filename: &str) -> Result<Value, Error> {
+    let text = read_schema_file(filename)?;
+
+    if filename.to_lowercase().ends_with(".toml") {
+        toml::from_str(&text).map_err(|x| x.to_string())
+    } else {
+        serde_json::from_str(&text).map_err(|x| x.to_string())
+    }
+    .map_err(|error| Error::Config(format!("Invalid schema file {}. {}", filename, error)))
+}
+
+fn read_schema_file(filename: &str) -> Result<String, Error> {
+    fs::read_to_string(filename)
+        .map_err(|error| Error::Io(format!("Cannot read schema file {}. {}", filename, error)))
+}
+
+fn default_trim() -> bool {
+    true
+}
+
+// split a header into name, type and optional binary encoding
+// e.g. Blob:B:hex is (Blob, B, hex), Count:N is (Count, N, None)
+fn parse_annotation(column: &str) -> Option<(&str, ColumnType, Option<BinaryEncoding>)> {
+    let parse_type = |text: &str| {
+        text.parse::<ColumnType>()
+            .ok()
+            .filter(|column_type| column_type.to_string() == text)
+    };
+
+    let (rest, suffix) = column.rsplit_once(':')?;
+    if let Ok(encoding) = suffix.parse::<BinaryEncoding>() {
+        if let Some((column_name, type_text)) = rest.rsplit_once(':') {
+            match parse_type(type_text) {
+                Some(column_type @ ColumnType::B) | Some(column_type @ ColumnType::BS) => {
+                    return Some((column_name, column_type, Some(encoding)));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    parse_type(suffix).map(|column_type| (rest, column_type, None))
+}
+
+fn deserialize_columns<'de, D>(deserializer: D) -> Result<HashMap<String, ColumnSchema>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let definitions = HashMap::<String, ColumnDefinition>::deserialize(deserializer)?;
+    Ok(definitions
+        .into_iter()
+        .map(|(column_name, definition)| (column_name, definition.0))
+        .collect())
+}
+
+impl<'de> Deserialize<'de> for ColumnDefinition {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct ColumnDefinitionVisitor;
+
+        impl<'de> Visitor<'de> for ColumnDefinitionVisitor {
+            type Value = ColumnDefinition;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a type or a column definition")
+            }
+
+            fn visit_str<E: de::Error>(self, text: &str) -> Result<Self::Value, E> {
+                let column_type = text.parse().map_err(E::custom)?;
+                Ok(ColumnDefinition(ColumnSchema {
+                    column_type: Some(column_type),
+                    ..Default::default()
+                }))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let column_schema = ColumnSchema::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(ColumnDefinition(column_schema))
+            }
+        }
+
+        deserializer.deserialize_any(ColumnDefinitionVisitor)
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = String;
+
+    // case insensitive, e.g. "ss", "SS" and "Ss" are all string set
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_uppercase().as_str() {
+            "S" => Ok(ColumnType::S),
+            "N" => Ok(ColumnType::N),
+            "B" => Ok(ColumnType::B),
+            "BOOL" => Ok(ColumnType::Bool),
+            "NULL" => Ok(ColumnType::Null),
+            "SS" => Ok(ColumnType::SS),
+            "NS" => Ok(ColumnType::NS),
+            "BS" => Ok(ColumnType::BS),
+            "L" => Ok(ColumnType::L),
+            "M" => Ok(ColumnType::M),
+            "JSON" => Ok(ColumnType::Json),
+            "SKIP" => Ok(ColumnType::Skip),
+            _ => Err(format!(
+                "{} is not a valid type. Expected one of S, N, B, BOOL, NULL, SS, NS, BS, L, M, JSON, skip",
+                text
+            )),
+        }
+    }
+}
+
+impl FromStr for BinaryEncoding {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "utf8" => Ok(BinaryEncoding::Utf8),
+            "base64" => Ok(BinaryEncoding::Base64),
+            "base64url" => Ok(BinaryEncoding::Base64Url),
+            "hex" => Ok(BinaryEncoding::Hex),
+            _ => Err(format!("{} is not a valid encoding", text)),
+        }
+    }
+}
+
+impl fmt::Display for BinaryEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            BinaryEncoding::Utf8 => "utf8",
+            BinaryEncoding::Base64 => "base64",
+            BinaryEncoding::Base64Url => "base64url",
+            BinaryEncoding::Hex => "hex",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+impl Tokens {
+    // true or false, None if the text is neither
+    pub fn parse_bool(&self, text: &str) -> Option<bool> {
+        if is_token(&self.true_tokens, "true", text) {
+            Some(true)
+        } else if is_token(&self.false_tokens, "false", text) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_null(&self, text: &str) -> bool {
+        is_token(&self.null_tokens, "null", text)
+    }
+
+    // strings in json keep their type unless words are declared, e.g. {"active": "Y"}
+    pub fn parse_json_string(&self, text: &str) -> Option<Value> {
+        let parsed_as_bool = self.parse_bool(text);
+
+        if self.null_tokens.is_some() && self.is_null(text) {
+            Some(Value::Null)
+        } else if self.true_tokens.is_some() && parsed_as_bool == Some(true) {
+            Some(Value::Bool(true))
+        } else if self.false_tokens.is_some() && parsed_as_bool == Some(false) {
+            Some(Value::Bool(false))
+        } else {
+            None
+        }
+    }
+}
+
+// declared words are case insensitive, the default word is not (same as before words are declared)
+fn is_token(tokens: &Option<Vec<String>>, default: &str, text: &str) -> bool {
+    match tokens {
+        Some(tokens) => tokens
+            .iter()
+            .any(|x| x.to_lowercase() == text.to_lowercase()),
+        None => text == default,
+    }
+}
+
+impl FromStr for EmptyPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "omit" => Ok(EmptyPolicy::Omit),
+            "null" => Ok(EmptyPolicy::Null),
+            "string" => Ok(EmptyPolicy::String),
+            "reject" => Ok(EmptyPolicy::Reject),
+            _ => Err(format!(
+                "{} is not a valid empty policy. Expected one of omit, null, string, reject",
+                text
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for ColumnType {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            ColumnType::S => "S",
+            ColumnType::N => "N",
+            ColumnType::B => "B",
+            ColumnType::Bool => "BOOL",
+            ColumnType::Null => "NULL",
+            ColumnType::SS => "SS",
+            ColumnType::NS => "NS",
+            ColumnType::BS => "BS",
+            ColumnType::L => "L",
+            ColumnType::M => "M",
+            ColumnType::Json => "JSON",
+            ColumnType::Skip => "skip",
+        };
+        write!(f, "{}", text)
+    }
+}
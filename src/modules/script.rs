@@ -0,0 +1,151 @@
+use super::utility::Row;
+use rhai::{serde::from_dynamic, Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde_json::{to_string, Value};
+
+/*
+    a rhai script which transforms each row before it is typed, e.g.
+        fn transform(row) {
+            if row.status == "deleted" {
+                return ();
+            }
+            row.name = row.name.to_upper();
+            [row, #{ PK: "EMAIL#" + row.email, SK: row.id }]
+        }
+    transform gets a map of column name -> cell text (() for missing cells), and returns a map (one row),
+    an array of maps (several rows) or () (no row), or throws an error for the row
+*/
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+// a row returned by the script, column name -> cell text (None for null)
+pub type ScriptRow = Vec<(String, Option<String>)>;
+
+// limits of each call of transform, so a script which loops forever fails its row
+// rather than hanging the import
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 64;
+
+impl Script {
+    pub fn load(filename: &str) -> Result<Script, String> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS);
+        let ast = engine
+            .compile_file(filename.into())
+            .map_err(|error| error.to_string())?;
+
+        if !ast
+            .iter_functions()
+            .any(|x| x.name == "transform" && x.params.len() == 1)
+        {
+            return Err("transform(row) is not defined".to_string());
+        }
+
+        Ok(Script { engine, ast })
+    }
+
+    // rows built from a csv row, returns error of the script, with its position in the script
+    pub fn transform(
+        &self,
+        column_names: &[String],
+        row: &[Option<String>],
+    ) -> Result<Vec<ScriptRow>, String> {
+        let map: Map = column_names
+            .iter()
+            .zip(row)
+            .map(|(column_name, cell)| {
+                let value = match cell {
+                    Some(text) => text.into(),
+                    None => Dynamic::UNIT,
+                };
+                (column_name.into(), value)
+            })
+            .collect();
+
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, "transform", (map,))
+            .map_err(|error: Box<EvalAltResult>| match error.unwrap_inner() {
+                EvalAltResult::ErrorTooManyOperations(_) => {
+                    format!("transform exceeds {} operations", MAX_OPERATIONS)
+                }
+                EvalAltResult::ErrorStackOverflow(_) => {
+                    format!(
+                        "transform exceeds {} levels of function calls",
+                        MAX_CALL_LEVELS
+                    )
+                }
+                _ => error.to_string(),
+            })?;
+
+        if result.is_unit() {
+            Ok(Vec::new())
+        } else if result.is_map() {
+            Ok(vec![to_script_row(result.cast::<Map>())?])
+        } else if result.is_array() {
+            result
+                .cast::<Array>()
+                .into_iter()
+                .map(|x| match x.try_cast::<Map>() {
+                    Some(map) => to_script_row(map),
+                    None => Err("transform must return maps in the array".to_string()),
+                })
+                .collect()
+        } else {
+            Err(format!(
+                "transform must return a map, an array of maps or (), instead of {}",
+                result.type_name()
+            ))
+        }
+    }
+}
+
+// columns returned by script are added to the end of header, in the order they are found
+// a column missing from a row is null, so it is left out of the item unless null is allowed
+pub fn build_rows(
+    mut column_names: Vec<String>,
+    script_rows: Vec<(ScriptRow, u64)>,
+) -> (Vec<String>, Vec<(Row, u64)>) {
+    for (script_row, _) in &script_rows {
+        for (column_name, _) in script_row {
+            if !column_names.contains(column_name) {
+                column_names.push(column_name.to_owned());
+            }
+        }
+    }
+
+    let null_row: Row = vec![None; column_names.len()];
+    let rows = script_rows
+        .into_iter()
+        .map(|(script_row, line)| {
+            let mut row = null_row.to_owned();
+            for (column_name, cell) in script_row {
+                let i = column_names.iter().position(|x| *x == column_name).unwrap();
+                row[i] = cell;
+            }
+            (row, line)
+        })
+        .collect();
+
+    (column_names, rows)
+}
+
+// strings are cells as they are, () is null,
+// other values are json, e.g. 1.5, true or [1, 2]
+fn to_script_row(map: Map) -> Result<ScriptRow, String> {
+    map.into_iter()
+        .map(|(column_name, value)| {
+            let json: Value = from_dynamic(&value)
+                .map_err(|error| format!("{} cannot be converted. {}", column_name, error))?;
+            let text = match json {
+                Value::String(text) => Some(text),
+                Value::Null => None,
+                json => Some(to_string(&json).unwrap()),
+            };
+            Ok((column_name.to_string(), text))
+        })
+        .collect()
+}
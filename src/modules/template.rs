@@ -0,0 +1,300 @@
+use super::schema::{Schema, Tokens};
+use super::timestamp::{SourceTimezone, TimestampFormat, TimestampOutput};
+use chrono::format::{Item, StrftimeItems};
+
+/*
+    an attribute built from other columns of the same row, e.g.
+        USER#{user_id}
+        ORDER#{order_date|date:%Y%m%d}#{order_id|pad:8}
+    helpers are applied from left to right, {{ and }} are literal braces
+*/
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    Column {
+        index: usize,
+        helpers: Vec<Helper>,
+
+        // words of the column, to check null
+        tokens: Tokens,
+    },
+}
+
+enum Helper {
+    Lower,
+    Upper,
+    Trim,
+
+    // zeros added to the left, up to the width
+    Pad(usize),
+
+    // parsed by the timestamp format of the column (rfc3339 by default),
+    // then formatted in UTC with a strftime pattern
+    Date(TimestampFormat, String),
+}
+
+impl Template {
+    // parse a template, and find the columns it refers to
+    pub fn new(text: &str, column_names: &[String], schema: &Schema) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("{} has an unclosed brace", text))?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(literal.split_off(0)));
+                    }
+                    parts.push(parse_placeholder(&rest[..end], column_names, schema)?);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(format!("{} has an unopened brace", text)),
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+
+        Ok(Template { parts })
+    }
+
+    // if any column in the template is empty, null or missing, the result is empty,
+    // so it follows the empty policy of the attribute
+    pub fn render(&self, row: &[Option<String>]) -> Result<String, String> {
+        let mut result = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => result.push_str(text),
+                Part::Column {
+                    index,
+                    helpers,
+                    tokens,
+                } => {
+                    let mut value = match &row[*index] {
+                        Some(value) if !value.is_empty() && !tokens.is_null(value) => {
+                            value.to_owned()
+                        }
+                        _ => return Ok(String::new()),
+                    };
+                    for helper in helpers {
+                        value = helper.apply(value)?;
+                    }
+                    result.push_str(&value);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+impl Helper {
+    fn apply(&self, value: String) -> Result<String, String> {
+        match self {
+            Helper::Lower => Ok(value.to_lowercase()),
+            Helper::Upper => Ok(value.to_uppercase()),
+            Helper::Trim => Ok(value.trim().to_string()),
+            Helper::Pad(width) => Ok(format!("{:0>width$}", value, width = width)),
+            Helper::Date(timestamp, pattern) => timestamp
+                .parse(&value)
+                .map(|x| x.format(pattern).to_string()),
+        }
+    }
+}
+
+// e.g. order_id|pad:8, or order_date|date:%Y-%m-%d
+fn parse_placeholder(text: &str, column_names: &[String], schema: &Schema) -> Result<Part, String> {
+    let mut items = text.split('|');
+    let column_name = items.next().unwrap().trim();
+    let index = column_names
+        .iter()
+        .position(|x| x == column_name)
+        .ok_or_else(|| format!("Column {} is not found in csv", column_name))?;
+
+    let helpers = items
+        .map(|item| {
+            let (name, arg) = match item.split_once(':') {
+                Some((name, arg)) => (name.trim(), Some(arg)),
+                None => (item.trim(), None),
+            };
+
+            match (name, arg) {
+                ("lower", None) => Ok(Helper::Lower),
+                ("upper", None) => Ok(Helper::Upper),
+                ("trim", None) => Ok(Helper::Trim),
+                ("pad", Some(width)) => width
+                    .trim()
+                    .parse()
+                    .map(Helper::Pad)
+                    .map_err(|_| format!("{} is not a valid width of pad", width)),
+                ("date", Some(pattern)) => {
+                    if StrftimeItems::new(pattern).any(|x| x == Item::Error) {
+                        return Err(format!("{} is not a valid date pattern", pattern));
+                    }
+                    let timestamp = schema
+                        .column(column_name)
+                        .and_then(|x| x.timestamp.clone())
+                        .unwrap_or_else(|| TimestampFormat {
+                            format: "rfc3339".to_string(),
+                            timezone: SourceTimezone::default(),
+                            output: TimestampOutput::default(),
+                        });
+                    Ok(Helper::Date(timestamp, pattern.to_string()))
+                }
+                _ => Err(format!(
+                    "{} is not a valid helper. Expected lower, upper, trim, pad:[width] or date:[pattern]",
+                    item
+                )),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Part::Column {
+        index,
+        helpers,
+        tokens: schema.column_tokens(column_name),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::from_str;
+
+    fn render(text: &str, schema: &str, row: &[(&str, Option<&str>)]) -> Result<String, String> {
+        let column_names: Vec<_> = row.iter().map(|x| x.0.to_string()).collect();
+        let row: Vec<_> = row.iter().map(|x| x.1.map(String::from)).collect();
+        let schema: Schema = from_str(schema).unwrap();
+        Template::new(text, &column_names, &schema)?.render(&row)
+    }
+
+    fn new(text: &str) -> Result<(), String> {
+        let column_names = vec!["id".to_string()];
+        Template::new(text, &column_names, &Schema::default()).map(|_| ())
+    }
+
+    #[test]
+    fn columns_and_literal_text() {
+        let row = [("id", Some("42")), ("kind", Some("order"))];
+        assert_eq!(render("USER#{id}", "{}", &row), Ok("USER#42".to_string()));
+        assert_eq!(
+            render("{kind}#{id}#{ id }", "{}", &row),
+            Ok("order#42#42".to_string())
+        );
+        assert_eq!(render("static", "{}", &row), Ok("static".to_string()));
+        assert_eq!(
+            render("{{literal}}#{id}", "{}", &row),
+            Ok("{literal}#42".to_string())
+        );
+    }
+
+    #[test]
+    fn helpers_are_applied_from_left_to_right() {
+        let row = [("name", Some("  Ada Lovelace ")), ("id", Some("42"))];
+        assert_eq!(
+            render("{name|lower}", "{}", &row),
+            Ok("  ada lovelace ".to_string())
+        );
+        assert_eq!(
+            render("{name|trim|upper}", "{}", &row),
+            Ok("ADA LOVELACE".to_string())
+        );
+        assert_eq!(render("{id|pad:8}", "{}", &row), Ok("00000042".to_string()));
+        assert_eq!(render("{id|pad: 1}", "{}", &row), Ok("42".to_string()));
+    }
+
+    #[test]
+    fn date_helper_uses_timestamp_of_column() {
+        let row = [("created", Some("2020-08-01T19:30:00+10:00"))];
+        assert_eq!(
+            render("{created|date:%Y%m%d-%H%M}", "{}", &row),
+            Ok("20200801-0930".to_string())
+        );
+
+        let schema = r#"{"columns": {"created": {"timestamp": {"format": "%d/%m/%Y", "timezone": "+10:00"}}}}"#;
+        let row = [("created", Some("01/08/2020"))];
+        assert_eq!(
+            render("{created|date:%Y-%m-%dT%H}", schema, &row),
+            Ok("2020-07-31T14".to_string())
+        );
+
+        let row = [("created", Some("yesterday"))];
+        assert_eq!(
+            render("{created|date:%Y}", "{}", &row),
+            Err("yesterday is not a valid timestamp of format rfc3339".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_null_or_missing_columns_give_empty_result() {
+        let schema = r#"{"columns": {"b": {"tokens": {"null": ["N/A"]}}}}"#;
+        for cell in [Some(""), Some("null"), None] {
+            let row = [("a", cell), ("b", Some("x"))];
+            assert_eq!(render("A#{a}#{b}", "{}", &row), Ok(String::new()));
+        }
+        let row = [("a", Some("1")), ("b", Some("n/a"))];
+        assert_eq!(render("A#{a}#{b}", schema, &row), Ok(String::new()));
+        let row = [("a", Some("1")), ("b", Some("null"))];
+        assert_eq!(
+            render("A#{a}#{b}", schema, &row),
+            Ok("A#1#null".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        assert_eq!(new("USER#{id}"), Ok(()));
+        assert_eq!(
+            new("USER#{id"),
+            Err("USER#{id has an unclosed brace".to_string())
+        );
+        assert_eq!(
+            new("USER#id}"),
+            Err("USER#id} has an unopened brace".to_string())
+        );
+        assert_eq!(
+            new("{name}"),
+            Err("Column name is not found in csv".to_string())
+        );
+        assert_eq!(
+            new("{id|reverse}"),
+            Err(
+                "reverse is not a valid helper. Expected lower, upper, trim, pad:[width] or date:[pattern]"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            new("{id|pad}"),
+            Err(
+                "pad is not a valid helper. Expected lower, upper, trim, pad:[width] or date:[pattern]"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            new("{id|pad:x}"),
+            Err("x is not a valid width of pad".to_string())
+        );
+        assert_eq!(
+            new("{id|date:%Q}"),
+            Err("%Q is not a valid date pattern".to_string())
+        );
+    }
+}
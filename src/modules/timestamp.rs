@@ -0,0 +1,414 @@
+use super::schema::ColumnType;
+use chrono::{
+    DateTime, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+/*
+    option of a timestamp column, e.g.
+    {"format": "%d/%m/%Y %H:%M", "timezone": "Australia/Sydney", "output": "epoch"}
+*/
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimestampFormat {
+    // rfc3339, rfc2822, epoch (seconds), epoch_millis, or a strftime pattern
+    pub format: String,
+
+    // timezone of values without offset, UTC by default
+    #[serde(default)]
+    pub timezone: SourceTimezone,
+
+    #[serde(default)]
+    pub output: TimestampOutput,
+}
+
+// either a fixed offset (e.g. +10:00) or a name in tz database (e.g. Australia/Sydney)
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum SourceTimezone {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampOutput {
+    // string in UTC, e.g. 2020-08-01T09:30:00Z
+    #[default]
+    Iso8601,
+
+    // number of seconds since 1970-01-01 UTC
+    Epoch,
+
+    // number of milliseconds since 1970-01-01 UTC
+    EpochMillis,
+}
+
+impl TimestampFormat {
+    // iso8601 is saved as string, epoch as number
+    pub fn output_type(&self) -> ColumnType {
+        match self.output {
+            TimestampOutput::Iso8601 => ColumnType::S,
+            TimestampOutput::Epoch | TimestampOutput::EpochMillis => ColumnType::N,
+        }
+    }
+
+    // parse the text in the given format, then convert it to the output format
+    pub fn normalize(&self, text: &str) -> Result<String, String> {
+        let datetime = self.parse(text)?;
+
+        Ok(match self.output {
+            TimestampOutput::Iso8601 => datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            TimestampOutput::Epoch => datetime.timestamp().to_string(),
+            TimestampOutput::EpochMillis => datetime.timestamp_millis().to_string(),
+        })
+    }
+
+    pub fn parse(&self, text: &str) -> Result<DateTime<Utc>, String> {
+        let invalid = || {
+            format!(
+                "{} is not a valid timestamp of format {}",
+                text, self.format
+            )
+        };
+        let text = text.trim();
+
+        match self.format.as_str() {
+            "rfc3339" => DateTime::parse_from_rfc3339(text)
+                .map(|x| x.with_timezone(&Utc))
+                .map_err(|_| invalid()),
+
+            "rfc2822" => DateTime::parse_from_rfc2822(text)
+                .map(|x| x.with_timezone(&Utc))
+                .map_err(|_| invalid()),
+
+            "epoch" => text
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| seconds.is_finite())
+                .and_then(|seconds| {
+                    Utc.timestamp_millis_opt((seconds * 1000.0).round() as i64)
+                        .single()
+                })
+                .ok_or_else(invalid),
+
+            "epoch_millis" => text
+                .parse::<i64>()
+                .ok()
+                .and_then(|millis| Utc.timestamp_millis_opt(millis).single())
+                .ok_or_else(invalid),
+
+            // strftime pattern, with or without offset (%z), with or without time
+            pattern => {
+                if let Ok(datetime) = DateTime::parse_from_str(text, pattern) {
+                    return Ok(datetime.with_timezone(&Utc));
+                }
+
+                let naive = NaiveDateTime::parse_from_str(text, pattern)
+                    .or_else(|_| {
+                        NaiveDate::parse_from_str(text, pattern)
+                            .map(|x| x.and_hms_opt(0, 0, 0).unwrap())
+                    })
+                    .map_err(|_| invalid())?;
+                self.timezone
+                    .to_utc(&naive)
+                    .ok_or_else(|| format!("{} does not exist in timezone {}", text, self.timezone))
+            }
+        }
+    }
+}
+
+// parse a relative offset into seconds, e.g. +30d, +12h, +90m, +3600s, +2w
+pub fn parse_offset(text: &str) -> Result<i64, String> {
+    let invalid = || {
+        format!(
+            "{} is not a valid offset. Examples: +3600s, +90m, +12h, +30d, +2w",
+            text
+        )
+    };
+
+    let body = text.strip_prefix('+').ok_or_else(invalid)?;
+    let unit_seconds = match body.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some('w') => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+
+    // digits only, e.g. +-1d and ++1d are not offsets
+    let digits = &body[..body.len() - 1];
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    digits
+        .parse::<i64>()
+        .ok()
+        .and_then(|x| x.checked_mul(unit_seconds))
+        .ok_or_else(invalid)
+}
+
+impl SourceTimezone {
+    // ambiguous local time (e.g. end of daylight saving) takes the earlier one
+    fn to_utc(&self, naive: &NaiveDateTime) -> Option<DateTime<Utc>> {
+        let earliest = |x: LocalResult<DateTime<Utc>>| match x {
+            LocalResult::Single(x) | LocalResult::Ambiguous(x, _) => Some(x),
+            LocalResult::None => None,
+        };
+
+        match self {
+            SourceTimezone::Fixed(offset) => earliest(
+                offset
+                    .from_local_datetime(naive)
+                    .map(|x| x.with_timezone(&Utc)),
+            ),
+            SourceTimezone::Named(tz) => {
+                earliest(tz.from_local_datetime(naive).map(|x| x.with_timezone(&Utc)))
+            }
+        }
+    }
+}
+
+impl Default for SourceTimezone {
+    fn default() -> Self {
+        SourceTimezone::Fixed(FixedOffset::east_opt(0).unwrap())
+    }
+}
+
+impl TryFrom<String> for SourceTimezone {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        if text.eq_ignore_ascii_case("utc") {
+            return Ok(SourceTimezone::default());
+        }
+
+        // parse offset such as +10:00 or -0530 by parsing a dummy date with it
+        if let Ok(datetime) =
+            DateTime::parse_from_str(&format!("2000-01-01 00:00 {}", text), "%Y-%m-%d %H:%M %z")
+        {
+            return Ok(SourceTimezone::Fixed(*datetime.offset()));
+        }
+
+        text.parse::<Tz>()
+            .map(SourceTimezone::Named)
+            .map_err(|_| format!("{} is not a valid timezone", text))
+    }
+}
+
+impl std::fmt::Display for SourceTimezone {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SourceTimezone::Fixed(offset) => write!(f, "{}", offset),
+            SourceTimezone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::from_str;
+
+    fn timestamp(json: &str) -> TimestampFormat {
+        from_str(json).unwrap()
+    }
+
+    fn normalize(json: &str, text: &str) -> Result<String, String> {
+        timestamp(json).normalize(text)
+    }
+
+    #[test]
+    fn parse_named_formats() {
+        let rfc3339 = r#"{"format": "rfc3339"}"#;
+        assert_eq!(
+            normalize(rfc3339, "2020-08-01T19:30:00+10:00"),
+            Ok("2020-08-01T09:30:00Z".to_string())
+        );
+        assert_eq!(
+            normalize(rfc3339, " 2020-08-01T09:30:00.250Z "),
+            Ok("2020-08-01T09:30:00.250Z".to_string())
+        );
+
+        assert_eq!(
+            normalize(r#"{"format": "rfc2822"}"#, "Sat, 1 Aug 2020 19:30:00 +1000"),
+            Ok("2020-08-01T09:30:00Z".to_string())
+        );
+
+        let epoch = r#"{"format": "epoch"}"#;
+        assert_eq!(
+            normalize(epoch, "1596274200"),
+            Ok("2020-08-01T09:30:00Z".to_string())
+        );
+        assert_eq!(
+            normalize(epoch, "1596274200.5"),
+            Ok("2020-08-01T09:30:00.500Z".to_string())
+        );
+
+        assert_eq!(
+            normalize(r#"{"format": "epoch_millis"}"#, "1596274200123"),
+            Ok("2020-08-01T09:30:00.123Z".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_strftime_patterns() {
+        // offset in the text comes first, the timezone is ignored
+        assert_eq!(
+            normalize(
+                r#"{"format": "%d/%m/%Y %H:%M %z", "timezone": "-05:00"}"#,
+                "01/08/2020 19:30 +1000"
+            ),
+            Ok("2020-08-01T09:30:00Z".to_string())
+        );
+
+        // without offset, UTC by default
+        assert_eq!(
+            normalize(r#"{"format": "%d/%m/%Y %H:%M"}"#, "01/08/2020 09:30"),
+            Ok("2020-08-01T09:30:00Z".to_string())
+        );
+
+        // date without time is midnight
+        assert_eq!(
+            normalize(r#"{"format": "%Y%m%d", "timezone": "+10:00"}"#, "20200801"),
+            Ok("2020-07-31T14:00:00Z".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_in_offset_and_named_timezones() {
+        let with_timezone = |timezone: &str, text: &str| {
+            normalize(
+                &format!(
+                    r#"{{"format": "%Y-%m-%d %H:%M", "timezone": "{}"}}"#,
+                    timezone
+                ),
+                text,
+            )
+        };
+
+        assert_eq!(
+            with_timezone("+10:00", "2020-08-01 19:30"),
+            Ok("2020-08-01T09:30:00Z".to_string())
+        );
+        assert_eq!(
+            with_timezone("-0530", "2020-08-01 04:00"),
+            Ok("2020-08-01T09:30:00Z".to_string())
+        );
+        assert_eq!(
+            with_timezone("UTC", "2020-08-01 09:30"),
+            Ok("2020-08-01T09:30:00Z".to_string())
+        );
+
+        // daylight saving of a named timezone applies
+        assert_eq!(
+            with_timezone("Australia/Sydney", "2020-07-01 09:00"),
+            Ok("2020-06-30T23:00:00Z".to_string())
+        );
+        assert_eq!(
+            with_timezone("Australia/Sydney", "2020-01-01 09:00"),
+            Ok("2019-12-31T22:00:00Z".to_string())
+        );
+
+        // ambiguous time takes the earlier one, skipped time does not exist
+        assert_eq!(
+            with_timezone("Australia/Sydney", "2020-04-05 02:30"),
+            Ok("2020-04-04T15:30:00Z".to_string())
+        );
+        assert_eq!(
+            with_timezone("Australia/Sydney", "2020-10-04 02:30"),
+            Err("2020-10-04 02:30 does not exist in timezone Australia/Sydney".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_timezones_are_rejected() {
+        assert!(
+            from_str::<TimestampFormat>(r#"{"format": "%Y", "timezone": "Mars/Base"}"#).is_err()
+        );
+        assert!(from_str::<TimestampFormat>(r#"{"format": "%Y", "timezone": "+25:00"}"#).is_err());
+        assert_eq!(
+            SourceTimezone::try_from("Nowhere".to_string()).err(),
+            Some("Nowhere is not a valid timezone".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_text_is_rejected() {
+        for (json, text) in [
+            (r#"{"format": "rfc3339"}"#, "2020-08-01"),
+            (r#"{"format": "rfc2822"}"#, "2020-08-01T09:30:00Z"),
+            (r#"{"format": "epoch"}"#, "abc"),
+            (r#"{"format": "epoch"}"#, "inf"),
+            (r#"{"format": "epoch_millis"}"#, "1.5"),
+            (r#"{"format": "%d/%m/%Y"}"#, "2020-08-01"),
+            (r#"{"format": "%d/%m/%Y"}"#, "31/02/2020"),
+        ] {
+            let format = timestamp(json);
+            assert_eq!(
+                format.parse(text),
+                Err(format!(
+                    "{} is not a valid timestamp of format {}",
+                    text, format.format
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn output_formats() {
+        let text = "2020-08-01T09:30:00.250Z";
+        assert_eq!(
+            normalize(r#"{"format": "rfc3339", "output": "epoch"}"#, text),
+            Ok("1596274200".to_string())
+        );
+        assert_eq!(
+            normalize(r#"{"format": "rfc3339", "output": "epoch_millis"}"#, text),
+            Ok("1596274200250".to_string())
+        );
+        assert_eq!(
+            timestamp(r#"{"format": "rfc3339", "output": "epoch"}"#).output_type(),
+            ColumnType::N
+        );
+        assert_eq!(
+            timestamp(r#"{"format": "rfc3339"}"#).output_type(),
+            ColumnType::S
+        );
+    }
+
+    #[test]
+    fn parse_offsets() {
+        assert_eq!(parse_offset("+3600s"), Ok(3600));
+        assert_eq!(parse_offset("+90m"), Ok(90 * 60));
+        assert_eq!(parse_offset("+12h"), Ok(12 * 3600));
+        assert_eq!(parse_offset("+30d"), Ok(30 * 86400));
+        assert_eq!(parse_offset("+2w"), Ok(14 * 86400));
+        assert_eq!(parse_offset("+0d"), Ok(0));
+
+        for text in [
+            "30d",
+            "+d",
+            "+30",
+            "+30y",
+            "+-1d",
+            "++1d",
+            "+1.5d",
+            "",
+            "+",
+            "+9223372036854775807w",
+        ] {
+            assert_eq!(
+                parse_offset(text),
+                Err(format!(
+                    "{} is not a valid offset. Examples: +3600s, +90m, +12h, +30d, +2w",
+                    text
+                )),
+                "{}",
+                text
+            );
+        }
+    }
+}
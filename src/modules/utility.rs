@@ -1,95 +1,190 @@
-use csv::Reader;
-use std::{io, io::Write, process};
-
-// read csv, return header and content (in two different vecs)
-pub fn parse_csv(filename: String) -> (Vec<String>, Vec<Vec<String>>) {
+use super::error::{Error, Result};
+use csv::{ErrorKind, ReaderBuilder};
+use flate2::read::MultiGzDecoder;
+use rusoto_dynamodb::AttributeValue;
+use serde_json::{from_str, Value};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    io::{BufRead, BufReader, Read, Write},
+};
+
+// header, rows and the line number where each row starts
+pub type CsvContent = (Vec<String>, Vec<Vec<String>>, Vec<u64>);
+
+// a row enriched by lookups or built by script
+// a missing cell is None, e.g. columns without match in lookup files, and it's null whatever the type
+pub type Row = Vec<Option<String>>;
+
+// read csv, returns header, rows and the line number where each row starts
+// rows of different length from header are kept, so they can be reported with their line
+// rows which cannot be read (e.g. invalid utf8) are skipped with a reason
+pub fn parse_csv(filename: String) -> Result<CsvContent> {
     let mut header_vec = Vec::new();
     let mut rows_vec = Vec::new();
-    let mut reader = Reader::from_path(filename).expect("Cannot properly read csv file.");
-    let headers = reader.headers().expect("Invalid csv header.");
+    let mut lines_vec = Vec::new();
+    let mut reader = ReaderBuilder::new()
+        .flexible(true)
+        .from_path(&filename)
+        .map_err(|error| Error::Io(format!("Cannot read csv file {}. {}", filename, error)))?;
+    let headers = reader
+        .headers()
+        .map_err(|error| Error::Csv(format!("Invalid csv header in {}. {}", filename, error)))?;
 
     for header in headers {
         header_vec.push(header.to_owned());
     }
 
     for record in reader.records() {
-        let row = &record
-            .expect("Invalid csv format. Please ensure each row matches the header definition.");
+        let row = match record {
+            Ok(row) => row,
+            Err(error) => {
+                if let ErrorKind::Io(_) = error.kind() {
+                    return Err(Error::Io(format!(
+                        "Cannot read csv file {}. {}",
+                        filename, error
+                    )));
+                }
+                let line = error.position().map(|x| x.line()).unwrap_or_default();
+                println!(
+                    "Invalid csv at line {} of {}. Row ignored: {}",
+                    line, filename, error
+                );
+                continue;
+            }
+        };
         let mut row_vec = Vec::new();
 
-        for column in row {
+        for column in &row {
             row_vec.push(column.to_owned())
         }
 
         rows_vec.push(row_vec);
+        lines_vec.push(row.position().map(|x| x.line()).unwrap_or_default());
+    }
+
+    Ok((header_vec, rows_vec, lines_vec))
+}
+
+// read DynamoDB JSON lines, return items together with their original lines
+// gzip files (e.g. DynamoDB export to S3) are decompressed on the fly
+// lines which are not valid DynamoDB JSON are skipped with a reason
+pub fn parse_json_lines(
+    filename: String,
+) -> Result<Vec<(HashMap<String, AttributeValue>, String)>> {
+    let cannot_read =
+        |error: io::Error| Error::Io(format!("Cannot read json file {}. {}", filename, error));
+    let mut file = File::open(&filename).map_err(cannot_read)?;
+    let mut magic_number = [0; 2];
+    let is_gzip = file.read_exact(&mut magic_number).is_ok() && magic_number == [0x1f, 0x8b];
+    let file = File::open(&filename).map_err(cannot_read)?;
+    let reader: Box<dyn BufRead> = if is_gzip {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+
+    let mut items = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(cannot_read)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_json_item(&line) {
+            Some(item) => items.push((item, line)),
+            None => println!(
+                "Invalid DynamoDB JSON at line {}. Line ignored: {}",
+                i + 1,
+                line
+            ),
+        }
     }
 
-    (header_vec, rows_vec)
+    Ok(items)
+}
+
+// both {"Item": {...}} (DynamoDB export to S3) and the item itself are accepted
+fn parse_json_item(line: &str) -> Option<HashMap<String, AttributeValue>> {
+    let mut json: HashMap<String, Value> = from_str(line).ok()?;
+    if json.len() == 1 {
+        if let Some(Value::Object(item)) = json.remove("Item") {
+            return serde_json::from_value(Value::Object(item)).ok();
+        }
+    }
+    from_str(line).ok()
 }
 
 // read a non-negative integer, given specified range
-pub fn read_int(prompt_text: &str, lower_bound: usize, upper_bound: usize) -> usize {
+pub fn read_int(prompt_text: &str, lower_bound: usize, upper_bound: usize) -> Result<usize> {
     print!("{} ({}-{}):", prompt_text, lower_bound, upper_bound);
 
-    let mut text = String::new();
-    io::stdout().flush().unwrap();
-    io::stdin()
-        .read_line(&mut text)
-        .expect("Failed to read input.");
-
-    let n: usize = text
-        .trim()
-        .parse()
-        .expect("Error: Input is not a valid number.");
+    let text = read_line()?;
+    let n: usize = text.trim().parse().map_err(|_| {
+        Error::Config(format!(
+            "Invalid input: {} is not a valid number.",
+            text.trim()
+        ))
+    })?;
 
     check_range(n, lower_bound, upper_bound)
 }
 
 // read a string
-pub fn read_text(prompt_text: &str) -> String {
+pub fn read_text(prompt_text: &str) -> Result<String> {
     print!("{}:", prompt_text);
 
-    let mut text = String::new();
-    io::stdout().flush().unwrap();
-    io::stdin()
-        .read_line(&mut text)
-        .expect("Failed to read input.");
-
-    text.trim().to_owned()
+    Ok(read_line()?.trim().to_owned())
 }
 
 // read a boolean (yes or no)
-pub fn read_yes_or_no(prompt_text: &str, default: bool) -> bool {
+pub fn read_yes_or_no(prompt_text: &str, default: bool) -> Result<bool> {
     if default {
         print!("{} (Y/n):", prompt_text);
     } else {
         print!("{} (N/y):", prompt_text);
     }
 
-    let mut text = String::new();
-    io::stdout().flush().unwrap();
-    io::stdin()
-        .read_line(&mut text)
-        .expect("Failed to read input.");
-
-    let answer = text.trim().to_lowercase();
+    let answer = read_line()?.trim().to_lowercase();
 
     if answer.is_empty() {
-        default
+        Ok(default)
     } else {
-        answer.chars().nth(0).unwrap() == 'y'
+        Ok(answer.starts_with('y'))
+    }
+}
+
+// read a line from stdin, after the prompt is shown
+fn read_line() -> Result<String> {
+    let mut text = String::new();
+    io::stdout()
+        .flush()
+        .and_then(|_| io::stdin().read_line(&mut text))
+        .map_err(|error| Error::Io(format!("Failed to read input. {}", error)))?;
+
+    Ok(text)
+}
+
+// format a row as a line of csv
+// columns in the row will always be quoted
+pub fn format_csv_row(row: &[String]) -> String {
+    let mut columns = Vec::new();
+    for column in row {
+        // escape quotes, then add a pair of quotes at outermost layer
+        columns.push(format!("\"{}\"", column.replace("\"", "\"\"")));
     }
+    columns.join(",")
 }
 
-pub fn check_range(input: usize, lower_bound: usize, upper_bound: usize) -> usize {
+pub fn check_range(input: usize, lower_bound: usize, upper_bound: usize) -> Result<usize> {
     if input < lower_bound || input > upper_bound {
-        println!(
+        return Err(Error::Config(format!(
             "Invalid input: {} is not between {} and {}.",
             input, lower_bound, upper_bound
-        );
-        process::exit(-1);
+        )));
     }
-    input
+    Ok(input)
 }
 
 pub struct ProgressPrinter {
@@ -105,10 +200,12 @@ impl ProgressPrinter {
     pub fn new(total_count: usize) -> ProgressPrinter {
         ProgressPrinter {
             current_percentage: 0,
-            total_count: total_count,
+            total_count,
         }
     }
 
+    // is_multiple_of would need Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
     pub fn update_progress(&mut self, updated_count: usize) {
         let updated_percentage =
             (100.0 * updated_count as f64 / self.total_count as f64).floor() as usize;
@@ -116,7 +213,7 @@ impl ProgressPrinter {
         while self.current_percentage < updated_percentage && self.current_percentage <= 100 {
             self.current_percentage += 1;
             print!("=");
-            io::stdout().flush().unwrap();
+            io::stdout().flush().unwrap_or_default();
             if self.current_percentage % 10 == 0 {
                 println!(":{}%", self.current_percentage);
             }
@@ -0,0 +1,140 @@
+use super::number::{check_number, compare_numbers};
+use super::schema::{Schema, Tokens, ValidationRule};
+use jsonschema::JSONSchema;
+use regex::Regex;
+use serde_json::{from_str, Value};
+use std::cmp::Ordering;
+
+// validation rules of columns in csv, compiled once
+pub struct Validator {
+    columns: Vec<ColumnValidator>,
+}
+
+struct ColumnValidator {
+    index: usize,
+    column_name: String,
+    rule: ValidationRule,
+    pattern: Option<Regex>,
+    json_schema: Option<JSONSchema>,
+
+    // words of null, which are empty as far as rules are concerned
+    tokens: Tokens,
+}
+
+impl Validator {
+    // rules of columns (after mapping, including lookup columns)
+    // rules of columns which are not in csv are ignored
+    pub fn new(schema: &Schema, column_names: &[String]) -> Result<Validator, String> {
+        let mut columns = Vec::new();
+
+        for (index, column_name) in column_names.iter().enumerate() {
+            let rule = match schema.column(column_name).and_then(|x| x.validate.as_ref()) {
+                Some(rule) => rule,
+                None => continue,
+            };
+            let invalid = |reason: String| format!("Column {}: {}", column_name, reason);
+
+            let pattern = match &rule.pattern {
+                Some(pattern) => Some(
+                    Regex::new(&format!("^(?:{})$", pattern))
+                        .map_err(|error| invalid(format!("invalid pattern. {}", error)))?,
+                ),
+                None => None,
+            };
+            let json_schema = match &rule.json_schema {
+                Some(json_schema) => Some(
+                    JSONSchema::compile(json_schema)
+                        .map_err(|error| invalid(format!("invalid json schema. {}", error)))?,
+                ),
+                None => None,
+            };
+            for bound in rule.min.iter().chain(&rule.max) {
+                if !bound.is_finite() {
+                    return Err(invalid(format!("{} is not a valid bound", bound)));
+                }
+            }
+
+            columns.push(ColumnValidator {
+                index,
+                column_name: column_name.to_owned(),
+                rule: rule.clone(),
+                pattern,
+                json_schema,
+                tokens: schema.column_tokens(column_name),
+            });
+        }
+
+        Ok(Validator { columns })
+    }
+
+    // the first rule the row violates, e.g. Column age violates max 100
+    pub fn validate(&self, row: &[Option<String>]) -> Result<(), String> {
+        for column in &self.columns {
+            column
+                .validate(row[column.index].as_deref())
+                .map_err(|rule| format!("Column {} violates {}", column.column_name, rule))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ColumnValidator {
+    // returns the violated rule, a missing cell is null
+    fn validate(&self, cell: Option<&str>) -> Result<(), String> {
+        let rule = &self.rule;
+
+        let text = match cell {
+            Some(text) if !text.is_empty() && !self.tokens.is_null(text) => text,
+            _ if rule.required => return Err("required".to_string()),
+            _ => return Ok(()),
+        };
+
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(text) {
+                return Err(format!("pattern {}", rule.pattern.as_ref().unwrap()));
+            }
+        }
+
+        for (bound, rule_name, violation) in [
+            (rule.min, "min", Ordering::Less),
+            (rule.max, "max", Ordering::Greater),
+        ] {
+            if let Some(bound) = bound {
+                let rule_name = format!("{} {}", rule_name, bound);
+                if check_number(text).is_err() {
+                    return Err(format!("{} (not a valid number)", rule_name));
+                }
+                if compare_numbers(text, &bound.to_string()) == Ok(violation) {
+                    return Err(rule_name);
+                }
+            }
+        }
+
+        if let Some(max_length) = rule.max_length {
+            if text.chars().count() > max_length {
+                return Err(format!("max_length {}", max_length));
+            }
+        }
+
+        if let Some(allowed) = &rule.allowed {
+            if !allowed.iter().any(|x| x == text) {
+                return Err(format!("allowed values {}", allowed.join(", ")));
+            }
+        }
+
+        if let Some(json_schema) = &self.json_schema {
+            let json =
+                from_str::<Value>(text).map_err(|_| "json_schema (not valid json)".to_string())?;
+            let reasons: Vec<_> = match json_schema.validate(&json) {
+                Ok(_) => Vec::new(),
+                Err(errors) => errors.map(|x| x.to_string()).collect(),
+            };
+            if !reasons.is_empty() {
+                return Err(format!("json_schema ({})", reasons.join("; ")));
+            }
+        }
+
+        Ok(())
+    }
+}